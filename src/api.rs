@@ -0,0 +1,114 @@
+use crate::{LearnFS, do_lookup, backend::Backend, http::{self, Request, Response}};
+use fuse::FileType::Directory;
+use libc::{c_int, ENOENT, ENOTDIR, EPERM, EACCES, EINVAL, EINTR, ETIMEDOUT, EAGAIN, EFBIG};
+use serde_json::{json, Value};
+use std::{fs::OpenOptions, io::Read, os::unix::fs::{MetadataExt, OpenOptionsExt}, path::Path, sync::{Arc, Mutex}};
+
+// a JSON API over the same tree as the mount, at `/<path in the mount>` over the unix socket `Config::api_socket`
+// - GET a directory returns its entries, and the parsed `meta.json` or `课程信息.json` in it as `meta`
+// - GET a file returns its content as a string, which is base64 for content not in UTF-8, told by `encoding`
+// - POST `<homework>/submit` with `{"content": ..., "attachment": <local path>}` submits a homework
+// - POST `<discussion>/reply` with `{"content": ..., "reply_to": <reply file name>}` replies to a discussion or a reply
+// - POST `<homework or discussion>/refresh` refreshes it
+// like in the mount, a student is only accessible to the local user who logged it in (and root), who is told by the socket
+pub(crate) fn serve<B: Backend>(learn: Arc<Mutex<LearnFS<B>>>, socket: &Path) {
+  if let Err(e) = http::serve_unix(socket, |req, uid| {
+    let mut learn = learn.lock().unwrap();
+    // the caller of the last FUSE request is not related to this one
+    learn.runtime.caller = None;
+    respond(&mut learn, uid, req)
+  }) { warn!("api: {}", e); }
+}
+
+fn json_response(status: &'static str, v: Value) -> Response {
+  (status, vec![("Content-Type", "application/json; charset=utf-8".to_owned())], v.to_string().into_bytes())
+}
+
+fn error(e: c_int) -> Response {
+  let (status, msg) = match e {
+    ENOENT => ("404 Not Found", "not found"),
+    EACCES => ("403 Forbidden", "permission denied"),
+    ENOTDIR | EPERM | EINVAL => ("400 Bad Request", "invalid operation"),
+    EFBIG => ("413 Payload Too Large", "attachment too large"),
+    EAGAIN => ("503 Service Unavailable", "the server can't be reached"),
+    ETIMEDOUT | EINTR => ("504 Gateway Timeout", "the server didn't respond in time"),
+    _ => ("502 Bad Gateway", "failed to fetch from the server"),
+  };
+  json_response(status, json!({ "error": msg }))
+}
+
+pub(crate) fn respond<B: Backend>(learn: &mut LearnFS<B>, uid: u32, req: &Request) -> Response {
+  let path = req.path.trim_matches('/');
+  match req.method.as_str() {
+    "GET" => match get(learn, uid, path) { Ok(v) => json_response("200 OK", v), Err(e) => error(e) },
+    "POST" => match post(learn, uid, path, &req.body) { Ok(()) => json_response("200 OK", json!({ "ok": true })), Err(e) => error(e) },
+    _ => ("405 Method Not Allowed", vec![("Allow", "GET, POST".to_owned())], Vec::new()),
+  }
+}
+
+// the student is checked before anything in it is fetched; ino 1 is the root of the filesystem
+fn resolve<B: Backend>(learn: &mut LearnFS<B>, uid: u32, path: &str) -> Result<u64, c_int> {
+  let (user, rest) = match path.find('/') { Some(idx) => (&path[..idx], &path[idx + 1..]), None => (path, "") };
+  let user = learn.resolve(1, user)?;
+  if !learn.accessible(uid, user) { return Err(EACCES); }
+  learn.resolve(user, rest)
+}
+
+fn get<B: Backend>(learn: &mut LearnFS<B>, uid: u32, path: &str) -> Result<Value, c_int> {
+  let ino = resolve(learn, uid, path)?;
+  if !matches!(learn.attr(ino).kind, Directory) {
+    learn.load(ino)?;
+    let content = learn.data(ino).map(|x| match std::str::from_utf8(x) {
+      Ok(s) => json!({ "content": s, "encoding": "utf-8" }),
+      Err(_) => json!({ "content": http::base64(x), "encoding": "base64" }),
+    });
+    learn.unload(ino);
+    return content.ok_or(EPERM);
+  }
+  let mut children = learn.listing(ino)?;
+  children.retain(|&(_, x)| learn.accessible(uid, x));
+  let mut meta = Value::Null;
+  let mut entries = Vec::new();
+  for (name, x) in &children {
+    let attr = learn.attr(*x);
+    entries.push(json!({ "name": name, "dir": matches!(attr.kind, Directory), "size": attr.size }));
+    if name == "meta.json" || name == learn.names.course_info_json {
+      meta = learn.data(*x).and_then(|x| serde_json::from_slice(x).ok()).unwrap_or(Value::Null);
+    }
+  }
+  Ok(json!({ "entries": entries, "meta": meta }))
+}
+
+fn post<B: Backend>(learn: &mut LearnFS<B>, uid: u32, path: &str, body: &[u8]) -> Result<(), c_int> {
+  let (target, action) = match path.rfind('/') { Some(idx) => (&path[..idx], &path[idx + 1..]), None => return Err(ENOENT) };
+  let body: Value = if body.is_empty() { Value::Null } else { serde_json::from_slice(body).map_err(|_| EINVAL)? };
+  let content = body["content"].as_str().unwrap_or("").to_owned();
+  let ino = resolve(learn, uid, target)?;
+  match action {
+    "submit" => {
+      let file = match body["attachment"].as_str() {
+        Some(path) => {
+          // it is read by the user running the mount, so another user may only send their own files
+          // the file is opened once, then checked and read through the same handle, so it can't be swapped in between;
+          // a symlink is not followed, and a fifo doesn't block the server
+          let mut f = OpenOptions::new().read(true).custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK).open(path)
+            .map_err(|e| if e.raw_os_error() == Some(libc::ELOOP) { EACCES } else { ENOENT })?;
+          let meta = f.metadata().map_err(|_| EINVAL)?;
+          if !meta.is_file() { return Err(EINVAL); }
+          if uid != 0 && uid != learn.uid && meta.uid() != uid { return Err(EACCES); }
+          let mut data = Vec::new();
+          f.read_to_end(&mut data).map_err(|_| EINVAL)?;
+          Some((Path::new(path).file_name().and_then(|x| x.to_str()).unwrap_or(path), data))
+        }
+        None => None,
+      };
+      learn.submit(ino, content, file)
+    }
+    "reply" => learn.reply_discussion(ino, body["reply_to"].as_str(), content, None),
+    "refresh" => {
+      let refresh = do_lookup(&learn.children(ino)?, learn.names.refresh).ok_or(EINVAL)?;
+      learn.refresh(refresh)
+    }
+    _ => Err(ENOENT),
+  }
+}
@@ -0,0 +1,289 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use libc::{c_int, EACCES, EAGAIN, EFBIG, EINTR, EIO, ENOENT, ETIMEDOUT};
+use std::{error::Error as StdError, fmt, future::Future, time::Duration};
+use tokio::runtime::Runtime;
+use thu_learn_helper::{LearnHelper, types::{Course, Homework, Notification, File, Discussion, DiscussionReply, Error as HelperError}};
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+pub type BackendResult<T> = Result<T, BoxError>;
+
+// the source of everything in `LearnFS`, `LearnHelper` (the real server) is the default one
+// ids are the ones in the returned items, `LearnFS` never constructs them
+#[async_trait]
+pub trait Backend: Send + Sync + Sized + 'static {
+  async fn login(username: &str, password: &str) -> BackendResult<Self>;
+  async fn logout(&self) -> BackendResult<()>;
+  // the latest semester first
+  async fn semester_id_list(&self) -> BackendResult<Vec<String>>;
+  async fn course_list(&self, semester: &str) -> BackendResult<Vec<Course>>;
+  async fn homework_list(&self, course: &str) -> BackendResult<Vec<Homework>>;
+  async fn notification_list(&self, course: &str) -> BackendResult<Vec<Notification>>;
+  async fn file_list(&self, course: &str) -> BackendResult<Vec<File>>;
+  async fn discussion_list(&self, course: &str) -> BackendResult<Vec<Discussion>>;
+  async fn discussion_replies(&self, course: &str, discussion: &str, board: &str) -> BackendResult<Vec<DiscussionReply>>;
+  // `file` is the name and content of the attachment
+  async fn submit_homework(&self, student_homework: &str, content: String, file: Option<(&str, Vec<u8>)>) -> BackendResult<()>;
+  // `reply` is the id of the reply being replied to, `None` for the discussion itself
+  async fn reply_discussion(&self, course: &str, discussion: &str, content: String, reply: Option<&str>, file: Option<(&str, Vec<u8>)>) -> BackendResult<()>;
+  async fn delete_discussion_reply(&self, course: &str, reply: &str) -> BackendResult<()>;
+  // the content at `url`, which is an attachment or a page marking an item as read
+  // a large attachment may take long, so `idle` limits the wait for each part of it instead of the whole download
+  async fn download(&self, url: &str, idle: Option<Duration>) -> BackendResult<Bytes>;
+}
+
+const LOGOUT_URL: &str = "https://learn.tsinghua.edu.cn/f/j_spring_security_logout";
+
+// where the login page posts its form (the same url `thu_learn_helper` logs in with)
+const LOGIN_FORM: &[u8] = b"id.tsinghua.edu.cn/do/off/ui/auth/login";
+
+// an expired session gets the html login page with `200` instead of a page, like `classify` finds for JSON
+pub fn is_login_page(data: &[u8]) -> bool {
+  let html = data.iter().find(|x| !x.is_ascii_whitespace()) == Some(&b'<');
+  html && data.windows(LOGIN_FORM.len()).any(|x| x == LOGIN_FORM)
+}
+
+// the first error of type `T` in the chain of `e` and its sources
+fn find<'a, T: StdError + 'static>(mut e: &'a (dyn StdError + 'static)) -> Option<&'a T> {
+  loop {
+    if let Some(x) = e.downcast_ref::<T>() { return Some(x); }
+    e = e.source()?;
+  }
+}
+
+// the kind of a failed request, `None` if there is no better errno than `EIO` for it (e.g. a `500` from the server)
+// `thu_learn_helper` returns the errors of `reqwest` and `serde_json` boxed, possibly as the source of its own ones
+pub fn classify(e: &(dyn StdError + 'static)) -> Option<BackendError> {
+  if let Some(e) = find::<reqwest::Error>(e) {
+    match e.status().map(|x| x.as_u16()) {
+      Some(401) | Some(403) => Some(BackendError::Auth),
+      Some(404) => Some(BackendError::NotFound),
+      Some(413) => Some(BackendError::TooLarge),
+      Some(_) => None,
+      None if e.is_timeout() => Some(BackendError::Timeout),
+      None if e.is_connect() => Some(BackendError::Network),
+      None => None,
+    }
+  } else if let Some(e) = find::<serde_json::Error>(e) {
+    // an expired session gets the html login page instead of JSON, which fails to parse at the first character (`<`)
+    // other parse errors (e.g. a missing field after the server changes its format) are not related to the session
+    if e.is_syntax() && e.column() == 1 { Some(BackendError::Auth) } else { None }
+  } else { None }
+}
+
+// an error of `thu_learn_helper`, as a `BackendError` if it is one of them
+fn boxed(e: HelperError) -> BoxError {
+  match classify(&*e) {
+    Some(kind) => (info!("{}", e), kind.into()).1,
+    None => e.to_string().into(),
+  }
+}
+
+// like `boxed`, for requests made here
+pub fn http_error(e: reqwest::Error) -> BoxError {
+  match classify(&e) {
+    Some(kind) => (info!("{}", e), kind.into()).1,
+    None => e.into(),
+  }
+}
+
+// GET `url` with the session of `client`, `idle` limits the wait for the response and for each chunk of its body
+pub async fn fetch(client: &reqwest::Client, url: &str, idle: Option<Duration>) -> BackendResult<Bytes> {
+  let mut res = within(idle, async { client.get(url).send().await.and_then(|x| x.error_for_status()).map_err(http_error) }).await?;
+  let mut body = Vec::new();
+  while let Some(chunk) = within(idle, async { res.chunk().await.map_err(http_error) }).await? {
+    body.extend_from_slice(&chunk);
+  }
+  Ok(body.into())
+}
+
+// `request`, failing with `Timeout` if it takes longer than `timeout`
+async fn within<T>(timeout: Option<Duration>, request: impl Future<Output = BackendResult<T>>) -> BackendResult<T> {
+  match timeout {
+    Some(t) => tokio::time::timeout(t, request).await.unwrap_or_else(|_| Err(BackendError::Timeout.into())),
+    None => request.await,
+  }
+}
+
+#[async_trait]
+impl Backend for LearnHelper {
+  // an error from neither `reqwest` nor `serde_json` is `thu_learn_helper` rejecting the response to the login form,
+  // which means a wrong user name or password; failures of the network or the server are kept as they are
+  async fn login(username: &str, password: &str) -> BackendResult<Self> {
+    LearnHelper::login(username, password).await.map_err(|e| {
+      if find::<reqwest::Error>(&*e).is_none() && find::<serde_json::Error>(&*e).is_none() {
+        (info!("{}", e), BackendError::Auth.into()).1
+      } else { boxed(e) }
+    })
+  }
+
+  async fn logout(&self) -> BackendResult<()> {
+    fetch(&self.0, LOGOUT_URL, None).await.map(|_| ())
+  }
+
+  async fn semester_id_list(&self) -> BackendResult<Vec<String>> {
+    LearnHelper::semester_id_list(self).await.map_err(boxed)
+  }
+
+  async fn course_list(&self, semester: &str) -> BackendResult<Vec<Course>> {
+    LearnHelper::course_list(self, semester).await.map_err(boxed)
+  }
+
+  async fn homework_list(&self, course: &str) -> BackendResult<Vec<Homework>> {
+    LearnHelper::homework_list(self, course).await.map_err(boxed)
+  }
+
+  async fn notification_list(&self, course: &str) -> BackendResult<Vec<Notification>> {
+    LearnHelper::notification_list(self, course).await.map_err(boxed)
+  }
+
+  async fn file_list(&self, course: &str) -> BackendResult<Vec<File>> {
+    LearnHelper::file_list(self, course).await.map_err(boxed)
+  }
+
+  async fn discussion_list(&self, course: &str) -> BackendResult<Vec<Discussion>> {
+    LearnHelper::discussion_list(self, course).await.map_err(boxed)
+  }
+
+  async fn discussion_replies(&self, course: &str, discussion: &str, board: &str) -> BackendResult<Vec<DiscussionReply>> {
+    LearnHelper::discussion_replies(self, course, discussion, board).await.map_err(boxed)
+  }
+
+  async fn submit_homework(&self, student_homework: &str, content: String, file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    LearnHelper::submit_homework(self, student_homework, content, file).await.map(|_| ()).map_err(boxed)
+  }
+
+  async fn reply_discussion(&self, course: &str, discussion: &str, content: String, reply: Option<&str>, file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    LearnHelper::reply_discussion(self, course, discussion, content, reply, file).await.map(|_| ()).map_err(boxed)
+  }
+
+  async fn delete_discussion_reply(&self, course: &str, reply: &str) -> BackendResult<()> {
+    LearnHelper::delete_discussion_reply(self, course, reply).await.map(|_| ()).map_err(boxed)
+  }
+
+  async fn download(&self, url: &str, idle: Option<Duration>) -> BackendResult<Bytes> {
+    fetch(&self.0, url, idle).await
+  }
+}
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// the slowest upload (in bytes per second) which is not taken as stuck
+const MIN_UPLOAD_RATE: usize = 16 << 10;
+// how often the caller is checked for pending signals while waiting for the server
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
+
+// failures which frontends can tell apart, a backend returns other errors for anything else, which are `EIO`
+#[derive(Debug, PartialEq)]
+pub enum BackendError {
+  // a wrong user name or password, or an expired session
+  Auth,
+  // the item is deleted on the server
+  NotFound,
+  // the server can't be reached, trying again later may work
+  Network,
+  // the attachment is larger than the server accepts
+  TooLarge,
+  // the request took longer than `Config::timeout`
+  Timeout,
+  // the process waiting for the request is interrupted, see `Executor`
+  Interrupted,
+}
+
+impl fmt::Display for BackendError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(match self {
+      BackendError::Auth => "authentication failed",
+      BackendError::NotFound => "not found on the server",
+      BackendError::Network => "network unreachable",
+      BackendError::TooLarge => "attachment too large",
+      BackendError::Timeout => "request timed out",
+      BackendError::Interrupted => "request interrupted",
+    })
+  }
+}
+
+impl std::error::Error for BackendError {}
+
+// the errno of a failed request
+pub fn errno(e: &BoxError) -> c_int {
+  match e.downcast_ref::<BackendError>() {
+    Some(BackendError::Auth) => EACCES,
+    Some(BackendError::NotFound) => ENOENT,
+    Some(BackendError::Network) => EAGAIN,
+    Some(BackendError::TooLarge) => EFBIG,
+    Some(BackendError::Timeout) => ETIMEDOUT,
+    Some(BackendError::Interrupted) => EINTR,
+    None => EIO,
+  }
+}
+
+// runs requests to a backend
+// each request to the server is limited by `timeout`, but not a batch of them or a download, which may take long for a large course or attachment
+// an upload has no progress to observe, so it is limited by `upload_limit` as a whole
+// `fuse` handles requests one by one, and doesn't pass `FUSE_INTERRUPT` to the filesystem while a request is being handled,
+// so instead the process which made the request (`caller`) is checked for pending signals (e.g. Ctrl+C), which abort the request
+pub struct Executor {
+  runtime: Runtime,
+  timeout: Option<Duration>,
+  // set by the frontend before handling each request, `None` if there is no process to check
+  pub caller: Option<u32>,
+}
+
+impl Executor {
+  // `timeout` is in seconds, see `Config::timeout`
+  pub fn new(timeout: Option<u64>) -> Executor {
+    let timeout = match timeout { Some(0) => None, Some(x) => Some(Duration::from_secs(x)), None => Some(DEFAULT_TIMEOUT) };
+    Executor { runtime: Runtime::new().unwrap(), timeout, caller: None }
+  }
+
+  // the limit of a single request, which is passed to `Backend::download` as the limit of each part of it
+  pub fn timeout(&self) -> Option<Duration> { self.timeout }
+
+  // `request` limited by `timeout`, it should be a single request to the server without any attachment
+  pub fn limit<T>(&self, request: impl Future<Output = BackendResult<T>>) -> impl Future<Output = BackendResult<T>> {
+    within(self.timeout, request)
+  }
+
+  // run a single request limited by `timeout`
+  pub fn request<T>(&mut self, request: impl Future<Output = BackendResult<T>>) -> BackendResult<T> {
+    let request = self.limit(request);
+    self.block_on(request)
+  }
+
+  // the limit of an upload of `size` bytes: `timeout`, plus the time to send them at `MIN_UPLOAD_RATE`
+  pub fn upload_limit(&self, size: usize) -> Option<Duration> {
+    self.timeout.map(|t| t + Duration::from_secs((size / MIN_UPLOAD_RATE) as u64))
+  }
+
+  // run a single request uploading `size` bytes, limited by `upload_limit`
+  pub fn upload<T>(&mut self, size: usize, request: impl Future<Output = BackendResult<T>>) -> BackendResult<T> {
+    let request = within(self.upload_limit(size), request);
+    self.block_on(request)
+  }
+
+  // run requests which are limited separately by `limit` or by the backend, the whole of them is not limited
+  pub fn block_on<T>(&mut self, requests: impl Future<Output = BackendResult<T>>) -> BackendResult<T> {
+    let caller = self.caller;
+    self.runtime.block_on(async move {
+      let interrupted = async move {
+        loop {
+          tokio::time::delay_for(INTERRUPT_POLL).await;
+          if caller.map_or(false, signal_pending) { break; }
+        }
+      };
+      // the losing future is dropped, which cancels the requests
+      tokio::select! {
+        x = requests => x,
+        _ = interrupted => Err(BackendError::Interrupted.into()),
+      }
+    })
+  }
+}
+
+// whether `pid` has a signal which is pending, not blocked and not ignored, read from `/proc/<pid>/status`
+fn signal_pending(pid: u32) -> bool {
+  let status = match std::fs::read_to_string(format!("/proc/{}/status", pid)) { Ok(x) => x, Err(_) => return false };
+  let mask = |key: &str| status.lines().find_map(|x| x.strip_prefix(key)).and_then(|x| u64::from_str_radix(x.trim(), 16).ok()).unwrap_or(0);
+  (mask("SigPnd:") | mask("ShdPnd:")) & !mask("SigBlk:") & !mask("SigIgn:") != 0
+}
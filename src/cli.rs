@@ -0,0 +1,158 @@
+use crate::{LearnFS, InoInfo::*, iso, load_config, usage};
+use libc::{c_int, ENOENT, ENOTDIR};
+use serde_json::{json, Value};
+use std::{io::{self, Write, BufRead}, ffi::OsStr};
+
+// exit codes of the commands, 1 is for wrong usage
+const EXIT_NOT_FOUND: i32 = 2;
+const EXIT_FAILED: i32 = 3;
+const EXIT_WRONG_KIND: i32 = 4;
+
+// `args` are the arguments after the command, `--json` and `--config <file>` can be anywhere in them
+// the password is read from `LEARN_PASSWORD`, or from stdin with a prompt in stderr, so that stdout only contains the output
+pub fn run(cmd: &str, mut args: Vec<String>) -> i32 {
+  let json = args.iter().any(|x| x == "--json");
+  args.retain(|x| x != "--json");
+  let config = match args.iter().position(|x| x == "--config") {
+    Some(idx) if idx + 1 < args.len() => load_config(Some(OsStr::new(&args.drain(idx..idx + 2).nth(1).unwrap()))),
+    Some(_) => usage(),
+    None => load_config(None),
+  };
+  let (min, max) = match cmd { "ls" => (1, 2), "cat" => (2, 2), "due" => (1, 2), _ => (3, 4) };
+  if args.len() < min || args.len() > max { usage(); }
+  let mut learn: LearnFS = LearnFS::new(config);
+  let password = match std::env::var("LEARN_PASSWORD") {
+    Ok(x) => x,
+    Err(_) => match read_password(learn.names.password_prompt) {
+      Ok(x) => x,
+      Err(e) => return (eprintln!("无法读取密码：{}", e), EXIT_FAILED).1,
+    },
+  };
+  let uid = learn.uid;
+  let user = match learn.login(&args[0], &password, uid) {
+    Ok(x) => x,
+    Err(e) => return (eprintln!("登录失败：{}", e), EXIT_FAILED).1,
+  };
+  let mut c = Cli { learn, user, json };
+  let result = match cmd {
+    "ls" => c.ls(args.get(1).map_or("", String::as_str)),
+    "cat" => c.cat(&args[1]),
+    "due" => match args.get(1).map_or(Ok(7), |x| x.parse()) {
+      Ok(days) => c.due(days),
+      Err(_) => usage(),
+    },
+    _ => c.submit(&args[1], &args[2], args.get(3).map(String::as_str)),
+  };
+  match result {
+    Ok(()) => 0,
+    Err((path, e)) => {
+      let (msg, code) = match e {
+        ENOENT => ("不存在", EXIT_NOT_FOUND),
+        ENOTDIR => ("类型不符", EXIT_WRONG_KIND),
+        libc::ETIMEDOUT => ("获取超时", EXIT_FAILED),
+        libc::EAGAIN => ("网络不可用", EXIT_FAILED),
+        libc::EACCES => ("登录已失效", EXIT_FAILED),
+        libc::EFBIG => ("附件过大", EXIT_FAILED),
+        _ => ("获取失败", EXIT_FAILED),
+      };
+      eprintln!("{}：{}", path, msg);
+      code
+    }
+  }
+}
+
+fn read_password(prompt: &str) -> io::Result<String> {
+  eprint!("{}", prompt);
+  let mut password = String::new();
+  io::stdin().lock().read_line(&mut password)?;
+  Ok(password.trim().to_owned())
+}
+
+struct Cli {
+  learn: LearnFS,
+  user: u64,
+  json: bool,
+}
+
+// the path where the error occurs, and an errno
+type CliResult = Result<(), (String, c_int)>;
+
+impl Cli {
+  // `path` is relative to `<mount point>/<student id>`, like `2020-2021-秋/<course>/作业`
+  fn resolve(&mut self, path: &str) -> Result<u64, (String, c_int)> {
+    self.learn.resolve(self.user, path).map_err(|e| (path.to_owned(), e))
+  }
+
+  fn ls(&mut self, path: &str) -> CliResult {
+    let ino = self.resolve(path)?;
+    let children = self.learn.listing(ino).map_err(|e| (path.to_owned(), e))?;
+    let entries = children.into_iter().map(|(name, x)| (name, matches!(self.learn.attr(x).kind, fuse::FileType::Directory)));
+    if self.json {
+      println!("{}", Value::Array(entries.map(|(name, dir)| json!({ "name": name, "dir": dir })).collect()));
+    } else {
+      for (name, dir) in entries { println!("{}{}", name, if dir { "/" } else { "" }); }
+    }
+    Ok(())
+  }
+
+  fn cat(&mut self, path: &str) -> CliResult {
+    let ino = self.resolve(path)?;
+    self.learn.load(ino).map_err(|e| (path.to_owned(), e))?;
+    let data = self.learn.data(ino).ok_or((path.to_owned(), ENOTDIR))?;
+    if self.json {
+      println!("{}", json!({ "path": path, "content": String::from_utf8_lossy(data) }));
+    } else {
+      // a broken pipe (e.g. `| head`) is not an error of this command
+      let _ = io::stdout().write_all(data);
+    }
+    Ok(())
+  }
+
+  // homework with deadlines in the next `days` days, in the latest semester (which is listed first by the server)
+  fn due(&mut self, days: i64) -> CliResult {
+    let now = chrono::Local::now().naive_local();
+    let (from, to) = (iso(&now), iso(&(now + chrono::Duration::days(days))));
+    let semester = match self.learn.children(self.user).map_err(|e| (String::new(), e))?.first() {
+      Some(x) => x.clone(),
+      None => return Ok(()),
+    };
+    let mut due = Vec::new();
+    for (course, c) in self.learn.children(semester.1).map_err(|e| (semester.0.clone(), e))? {
+      if !matches!(self.learn.inos[c as usize], Course { .. }) { continue; }
+      // the first child of a `Course` is the `ItemList` of homework
+      for (title, h) in self.learn.children(c + 1).map_err(|e| (course.clone(), e))? {
+        if let Item(_, x) = &self.learn.inos[h as usize] {
+          let get = |k: &str| x.iter().find(|x| x.0 == k).map(|x| x.1.clone()).unwrap_or_default();
+          let deadline = get("deadline");
+          if from <= deadline && deadline <= to { due.push((deadline, course.clone(), title, get("submitted") == "true")); }
+        }
+      }
+    }
+    due.sort();
+    if self.json {
+      println!("{}", Value::Array(due.into_iter().map(|(deadline, course, title, submitted)|
+        json!({ "deadline": deadline, "course": course, "title": title, "submitted": submitted })).collect()));
+    } else {
+      let names = self.learn.names;
+      for (deadline, course, title, submitted) in due {
+        println!("{}  {}  {}/{}", deadline, if submitted { names.yes } else { names.no }, course, title);
+      }
+    }
+    Ok(())
+  }
+
+  // `path` is a homework directory, the attachment is read from the local filesystem
+  fn submit(&mut self, path: &str, content: &str, attachment: Option<&str>) -> CliResult {
+    let ino = self.resolve(path)?;
+    let file = match attachment {
+      Some(f) => match std::fs::read(f) {
+        Ok(x) => Some((f, x)),
+        Err(_) => return Err((f.to_owned(), ENOENT)),
+      },
+      None => None,
+    };
+    self.learn.submit(ino, content.to_owned(), file).map_err(|e| (path.to_owned(), e))?;
+    if self.json { println!("{}", json!({ "submitted": path })); }
+    Ok(())
+  }
+}
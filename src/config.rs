@@ -0,0 +1,86 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use crate::{locale::Locale, html::BodyFormat};
+
+// all fields have default values, so a config file only needs to contain the fields to change
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+  // `zh` or `en`, the language of all fixed names
+  pub locale: Locale,
+  // `text`, `markdown` or `html`, the format of descriptions, notification contents and discussion replies
+  pub body: BodyFormat,
+  pub naming: Naming,
+  pub listing: Listing,
+  // the unix socket of the JSON API server, which runs next to the mount; `None` to disable it
+  pub api_socket: Option<PathBuf>,
+  // seconds each request to the server may take before failing with `ETIMEDOUT`, 30 by default; 0 to wait forever
+  // for a download, it is the longest wait for each part of it instead; an upload may take one more second for every 16 KiB
+  pub timeout: Option<u64>,
+}
+
+// how the entries of each category in a course are listed, by `readdir` and the other frontends except `sync` and `history`
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Listing {
+  pub homework: ListOptions,
+  pub notification: ListOptions,
+  pub file: ListOptions,
+  pub discussion: ListOptions,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ListOptions {
+  pub sort: SortBy,
+  pub reverse: bool,
+  // only for homework
+  pub hide_submitted: bool,
+  // only for notifications and files
+  pub hide_read: bool,
+}
+
+// `None` keeps the order returned by the server
+// `Deadline` is only for homework, and `Unread` means "not submitted" for homework
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy { None, Deadline, PublishTime, Title, Unread }
+
+impl Default for SortBy {
+  fn default() -> Self { SortBy::None }
+}
+
+// templates for the names of entries, see `template::render` for the syntax
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Naming {
+  // variables: `title`, `assign_time`, `deadline`
+  pub homework: String,
+  // variables: `title`, `publish_time`, `publisher`
+  pub notification: String,
+  // variables: `title`, `upload_time`
+  pub file: String,
+  // variables: `floor`, `author`, `time`, the default depends on the locale
+  pub reply: Option<String>,
+  // a reply to a reply, variables: `floor`, `reply`, `author`, `time`, the default depends on the locale
+  pub sub_reply: Option<String>,
+}
+
+impl Default for Naming {
+  fn default() -> Self {
+    Naming {
+      homework: "{title}".to_owned(),
+      notification: "{title}".to_owned(),
+      file: "{title}".to_owned(),
+      reply: None,
+      sub_reply: None,
+    }
+  }
+}
+
+impl Config {
+  pub fn load(path: &Path) -> Result<Config, String> {
+    let s = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    toml::from_str(&s).map_err(|e| e.to_string())
+  }
+}
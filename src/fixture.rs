@@ -0,0 +1,270 @@
+use crate::{iso, backend::{Backend, BackendError::*, BackendResult}};
+use async_trait::async_trait;
+use bytes::Bytes;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+use std::{sync::{Mutex, atomic::{AtomicUsize, Ordering}}, time::Duration};
+use thu_learn_helper::types::{Course, Homework, Notification, File, Discussion, DiscussionReply};
+
+// the fixture file, it is read on every `login`
+pub const FIXTURE_ENV: &str = "LEARN_FIXTURE";
+
+// a backend serving a fixture file instead of the server, so that the tree can be built without an account
+// the file maps student ids to their data, items are in the serde form of `thu_learn_helper::types`:
+// { "<student id>": { "password": ..., "semesters": [<semester id>, ...], "courses": { <semester id>: [...] },
+//   "homework" / "notifications" / "files" / "discussions": { <course id>: [...] }, "replies": { <discussion id>: [...] },
+//   "downloads": { <url>: <content> } } }
+// a missing list is empty, and a missing download is an empty page; a download is a string, or an array of bytes if it isn't UTF-8
+// a fixture without `password` accepts any password, and the data at `*` (like the one written by `Recorder`) any student id
+// submitting and replying modify its own copy of the data, which is seen by the following calls (e.g. after `刷新`)
+pub struct Fixture {
+  student: String,
+  pub(crate) data: Mutex<Value>,
+  next_reply: AtomicUsize,
+  // all urls passed to `download`, in order
+  #[cfg(test)]
+  pub visited: Mutex<Vec<String>>,
+}
+
+impl Fixture {
+  fn list<T: DeserializeOwned>(&self, kind: &str, key: &str) -> BackendResult<Vec<T>> {
+    match &self.data.lock().unwrap()[kind][key] {
+      Value::Null => Ok(Vec::new()),
+      x => Ok(serde_json::from_value(x.clone())?),
+    }
+  }
+}
+
+fn now() -> String { iso(&chrono::Local::now().naive_local()) }
+
+#[async_trait]
+impl Backend for Fixture {
+  async fn login(username: &str, password: &str) -> BackendResult<Self> {
+    let path = std::env::var(FIXTURE_ENV).map_err(|_| format!("{} is not set", FIXTURE_ENV))?;
+    let mut all: Value = serde_json::from_slice(&std::fs::read(&path)?)?;
+    let key = if all.get(username).is_some() { username } else { "*" };
+    let data = all.get_mut(key).map(Value::take).ok_or(Auth)?;
+    if !data["password"].is_null() && data["password"] != password { return Err(Auth.into()); }
+    Ok(Fixture {
+      student: username.to_owned(),
+      data: Mutex::new(data),
+      next_reply: AtomicUsize::new(0),
+      #[cfg(test)]
+      visited: Mutex::new(Vec::new()),
+    })
+  }
+
+  async fn logout(&self) -> BackendResult<()> { Ok(()) }
+
+  async fn semester_id_list(&self) -> BackendResult<Vec<String>> {
+    Ok(serde_json::from_value(self.data.lock().unwrap()["semesters"].clone())?)
+  }
+
+  async fn course_list(&self, semester: &str) -> BackendResult<Vec<Course>> { self.list("courses", semester) }
+
+  async fn homework_list(&self, course: &str) -> BackendResult<Vec<Homework>> { self.list("homework", course) }
+
+  async fn notification_list(&self, course: &str) -> BackendResult<Vec<Notification>> { self.list("notifications", course) }
+
+  async fn file_list(&self, course: &str) -> BackendResult<Vec<File>> { self.list("files", course) }
+
+  async fn discussion_list(&self, course: &str) -> BackendResult<Vec<Discussion>> { self.list("discussions", course) }
+
+  async fn discussion_replies(&self, _course: &str, discussion: &str, _board: &str) -> BackendResult<Vec<DiscussionReply>> {
+    self.list("replies", discussion)
+  }
+
+  // the attachment becomes a download at `fixture://<student homework id>/<name>`
+  async fn submit_homework(&self, student_homework: &str, content: String, file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    let mut data = self.data.lock().unwrap();
+    let h = data["homework"].as_object_mut().into_iter().flat_map(|m| m.values_mut()).filter_map(Value::as_array_mut).flatten()
+      .find(|h| h["student_homework_id"] == student_homework).ok_or(NotFound)?;
+    h["submit_content"] = json!(content);
+    h["submit_time"] = json!(now());
+    if let Some((name, content)) = file {
+      let url = format!("fixture://{}/{}", student_homework, name);
+      h["detail"]["submit_attachment_name_url"] = json!([name, url]);
+      data["downloads"][url] = json!(String::from_utf8_lossy(&content));
+    }
+    Ok(())
+  }
+
+  // attachments of replies are dropped, `LearnFS` never shows them
+  async fn reply_discussion(&self, _course: &str, discussion: &str, content: String, reply: Option<&str>, _file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    let id = format!("fixture-reply-{}", self.next_reply.fetch_add(1, Ordering::Relaxed));
+    let mut new = json!({ "id": id, "author": self.student, "publish_time": now(), "content": content });
+    let mut data = self.data.lock().unwrap();
+    let replies = &mut data["replies"][discussion];
+    if replies.is_null() { *replies = json!([]); }
+    let replies = replies.as_array_mut().ok_or("broken fixture")?;
+    match reply {
+      Some(reply) => {
+        let r = replies.iter_mut().find(|r| r["id"] == reply).ok_or(NotFound)?;
+        r.get_mut("replies").and_then(Value::as_array_mut).ok_or("broken fixture")?.push(new);
+      }
+      None => {
+        new["replies"] = json!([]);
+        replies.push(new);
+      }
+    }
+    Ok(())
+  }
+
+  async fn delete_discussion_reply(&self, _course: &str, reply: &str) -> BackendResult<()> {
+    let mut data = self.data.lock().unwrap();
+    for replies in data["replies"].as_object_mut().into_iter().flat_map(|m| m.values_mut()).filter_map(Value::as_array_mut) {
+      if let Some(idx) = replies.iter().position(|r| r["id"] == reply) {
+        replies.remove(idx);
+        return Ok(());
+      }
+      for r in replies.iter_mut().filter_map(|r| r.get_mut("replies").and_then(Value::as_array_mut)) {
+        if let Some(idx) = r.iter().position(|r| r["id"] == reply) {
+          r.remove(idx);
+          return Ok(());
+        }
+      }
+    }
+    Err(NotFound.into())
+  }
+
+  async fn download(&self, url: &str, _idle: Option<Duration>) -> BackendResult<Bytes> {
+    #[cfg(test)]
+    self.visited.lock().unwrap().push(url.to_owned());
+    match &self.data.lock().unwrap()["downloads"][url] {
+      Value::String(x) => Ok(x.clone().into()),
+      Value::Null => (info!("{} is not in the fixture", url), Ok(Bytes::new())).1,
+      x => Ok(serde_json::from_value::<Vec<u8>>(x.clone())?.into()),
+    }
+  }
+}
+
+// the fixture file written by `Recorder`
+pub const RECORD_ENV: &str = "LEARN_RECORD";
+
+// a backend passing everything to `B`, and recording the results into a fixture file, so that a session can be replayed by `Fixture`
+// `thu_learn_helper` only returns parsed results, so they are recorded instead of the HTTP exchanges, and bugs in parsing aren't caught
+// it is meant to be attached to a bug report, so the student id, the password and the session cookies are never in it,
+// `PERSONAL` fields are replaced, and attachments are replaced by their sizes; what is left is the structure of the tree
+// the file is replaced after every recorded call, so that it survives a crash; a later student logging in starts it over
+// submitting, replying and deleting are not recorded, `Fixture` does them on its own copy of the data
+pub struct Recorder<B> {
+  inner: B,
+  path: String,
+  data: Mutex<Value>,
+}
+
+// fields of `thu_learn_helper::types` which tell who someone is, or what they wrote or got
+const PERSONAL: &[&str] = &["teacher_name", "teacher_number", "publisher", "author", "submit_content", "grade", "grader_name",
+  "grade_content", "submit_attachment_name_url", "grade_attachment_name_url"];
+
+// replace `PERSONAL` fields in `value` by values of the same types, so that it can still be deserialized
+fn redact(kind: &str, value: &mut Value) {
+  match value {
+    Value::Array(xs) => xs.iter_mut().for_each(|x| redact(kind, x)),
+    Value::Object(m) => for (k, v) in m.iter_mut() {
+      // the content of a reply is written by a student, unlike that of a notification
+      if PERSONAL.contains(&k.as_str()) || (kind == "replies" && k == "content") { blank(v); } else { redact(kind, v); }
+    }
+    _ => {}
+  }
+}
+
+fn blank(value: &mut Value) {
+  if value.is_number() {
+    *value = json!(0);
+    return;
+  }
+  match value {
+    Value::String(s) => *s = "redacted".to_owned(),
+    Value::Array(xs) => xs.iter_mut().for_each(blank),
+    Value::Object(m) => m.values_mut().for_each(blank),
+    _ => {}
+  }
+}
+
+impl<B> Recorder<B> {
+  fn record<T: Serialize>(&self, kind: &str, key: Option<&str>, result: BackendResult<T>) -> BackendResult<T> {
+    if let Ok(x) = &result {
+      // a failure to record is only logged, it doesn't affect the session
+      match serde_json::to_value(x) {
+        Ok(mut value) => {
+          redact(kind, &mut value);
+          let mut data = self.data.lock().unwrap();
+          match key { Some(key) => data[kind][key] = value, None => data[kind] = value }
+          self.flush(&data);
+        }
+        Err(e) => warn!("failed to record {}: {}", kind, e),
+      }
+    }
+    result
+  }
+
+  // written to a temporary file which then replaces the old one, so that a crash while writing leaves the last complete file
+  // it is called with `data` locked, so that concurrent calls don't write the temporary file at the same time
+  fn flush(&self, data: &Value) {
+    let tmp = format!("{}.tmp", self.path);
+    let written = serde_json::to_vec_pretty(&json!({ "*": data })).map_err(Into::into)
+      .and_then(|x| std::fs::write(&tmp, x)).and_then(|_| std::fs::rename(&tmp, &self.path));
+    if let Err(e) = written { warn!("failed to record into {}: {}", self.path, e); }
+  }
+}
+
+#[async_trait]
+impl<B: Backend> Backend for Recorder<B> {
+  async fn login(username: &str, password: &str) -> BackendResult<Self> {
+    let path = std::env::var(RECORD_ENV).map_err(|_| format!("{} is not set", RECORD_ENV))?;
+    Ok(Recorder { inner: B::login(username, password).await?, path, data: Mutex::new(json!({})) })
+  }
+
+  async fn logout(&self) -> BackendResult<()> { self.inner.logout().await }
+
+  async fn semester_id_list(&self) -> BackendResult<Vec<String>> {
+    self.record("semesters", None, self.inner.semester_id_list().await)
+  }
+
+  async fn course_list(&self, semester: &str) -> BackendResult<Vec<Course>> {
+    self.record("courses", Some(semester), self.inner.course_list(semester).await)
+  }
+
+  async fn homework_list(&self, course: &str) -> BackendResult<Vec<Homework>> {
+    self.record("homework", Some(course), self.inner.homework_list(course).await)
+  }
+
+  async fn notification_list(&self, course: &str) -> BackendResult<Vec<Notification>> {
+    self.record("notifications", Some(course), self.inner.notification_list(course).await)
+  }
+
+  async fn file_list(&self, course: &str) -> BackendResult<Vec<File>> {
+    self.record("files", Some(course), self.inner.file_list(course).await)
+  }
+
+  async fn discussion_list(&self, course: &str) -> BackendResult<Vec<Discussion>> {
+    self.record("discussions", Some(course), self.inner.discussion_list(course).await)
+  }
+
+  async fn discussion_replies(&self, course: &str, discussion: &str, board: &str) -> BackendResult<Vec<DiscussionReply>> {
+    self.record("replies", Some(discussion), self.inner.discussion_replies(course, discussion, board).await)
+  }
+
+  async fn submit_homework(&self, student_homework: &str, content: String, file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    self.inner.submit_homework(student_homework, content, file).await
+  }
+
+  async fn reply_discussion(&self, course: &str, discussion: &str, content: String, reply: Option<&str>, file: Option<(&str, Vec<u8>)>) -> BackendResult<()> {
+    self.inner.reply_discussion(course, discussion, content, reply, file).await
+  }
+
+  async fn delete_discussion_reply(&self, course: &str, reply: &str) -> BackendResult<()> {
+    self.inner.delete_discussion_reply(course, reply).await
+  }
+
+  async fn download(&self, url: &str, idle: Option<Duration>) -> BackendResult<Bytes> {
+    let result = self.inner.download(url, idle).await;
+    if let Ok(x) = &result {
+      let mut data = self.data.lock().unwrap();
+      data["downloads"][url] = json!(format!("redacted, {} bytes", x.len()));
+      self.flush(&data);
+    }
+    result
+  }
+}
@@ -0,0 +1,392 @@
+use super::*;
+use fuse::{Filesystem, Request, ReplyEntry, ReplyAttr, ReplyDirectory, ReplyData, ReplyWrite, ReplyOpen, ReplyEmpty, ReplyCreate, ReplyXattr};
+
+// the `fuse::Filesystem` frontend of `LearnFS`, the tree itself is built by methods in `main.rs`
+
+// all information are only valid when it is returned
+// in this way, when `open` modifies the size of a file, kernel will fetch the attr of this file immediately after `open`
+// so that subsequent `read` can provide correct amount of data
+const TTL: Duration = Duration::from_secs(0);
+
+macro_rules! unwrap {
+  ($res: expr, $reply: expr) => {
+    match $res { Ok(x) => x, Err(e) => return (warn!("line {}: {:?}", line!(), e), $reply.error(errno(&e.into()))).1 }
+  };
+}
+
+impl<B: Backend> LearnFS<B> {
+  fn reply_entry(&self, ino: Option<u64>, reply: ReplyEntry) {
+    if let Some(ino) = ino { reply.entry(&TTL, &self.attr(ino), 0); } else { reply.error(ENOENT); }
+  }
+}
+
+// every handler which may wait for the server sets `caller` first, so that the request is aborted if the process is interrupted
+// (`getattr`, `read`, `getxattr`, `listxattr`, `flush` and `fsync` never wait, so a stale `caller` is never checked)
+impl<B: Backend> Filesystem for LearnFS<B> {
+  fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    info!("lookup parent={} name={:?}", parent, name);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), parent) { return reply.error(EACCES); }
+    let ref name = name.to_string_lossy();
+    match &mut self.inos[parent as usize] {
+      // `ItemList` contains `Item`/`Discussion` and the aggregated `meta.json`
+      Root { users: m } | User { semesters: m, .. } | ItemList(m, _) => {
+        let ino = do_lookup(m, name);
+        self.reply_entry(ino, reply);
+      }
+      Semester { .. } => {
+        if let Err(e) = self.fetch_courses(parent) { return reply.error(e); }
+        match &self.inos[parent as usize] {
+          Semester { courses: m, .. } => self.reply_entry(do_lookup(m, name), reply),
+          _ => reply.error(EIO),
+        }
+      }
+      Item(m, _) => {
+        let ino = do_lookup(m, name);
+        self.reply_entry(ino, reply);
+      }
+      // we need to fetch replies in both `lookup` and `readdir`, the former will be first called when
+      // user opens a file; the latter will be first called when user types `ls`
+      Discussion { .. } => {
+        if let Err(e) = self.fetch_discussion_replies(parent) { return reply.error(e); }
+        match &self.inos[parent as usize] {
+          Discussion { replies: m, .. } => self.reply_entry(do_lookup(m, name), reply),
+          _ => reply.error(EIO),
+        }
+      }
+      // going into any child dir representing course content must first call `lookup`, so fill the content of them here
+      Course { .. } => {
+        if let Err(e) = self.fetch_course(parent) { return reply.error(e); }
+        self.reply_entry(do_lookup(course_children(self.names, parent), name), reply);
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+  fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
+    info!("getattr ino={}", ino);
+    if let Removed = self.inos[ino as usize] { return reply.error(ENOENT); }
+    reply.attr(&TTL, &self.attr(ino));
+  }
+
+  fn setattr(&mut self, req: &Request, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<SystemTime>, _mtime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
+    info!("setattr(forward to getattr) ino={}", ino);
+    self.getattr(req, ino, reply);
+  }
+
+  fn mkdir(&mut self, req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
+    info!("mkdir parent={} name={:?}", parent, name);
+    self.runtime.caller = Some(req.pid());
+    let name = name.to_string_lossy();
+    match &self.inos[parent as usize] {
+      Root { .. } => {
+        // we don't need to worry about duplication here, because a prior `lookup` call will prevent it
+        let password = unwrap!(get_password(req.pid(), self.names.password_prompt), reply);
+        let ino = unwrap!(self.login(&name, &password, req.uid()), reply);
+        reply.entry(&TTL, &self.attr(ino), 0);
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+
+  // called when removing a dir, now only useful for logging out a student
+  fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    info!("rmdir parent={} name={:?}", parent, name);
+    self.runtime.caller = Some(req.pid());
+    let name = name.to_string_lossy();
+    let user = match &self.inos[parent as usize] { Root { users } => do_lookup(users, &name), _ => return reply.error(EPERM) };
+    let user = if let Some(x) = user { x } else { return reply.error(ENOENT); };
+    if !self.accessible(req.uid(), user) { return reply.error(EACCES); }
+    if let User { client, .. } = &self.inos[user as usize] {
+      // failing to log out doesn't prevent releasing local resources, the session will expire anyway
+      if let Err(e) = self.runtime.request(client.logout()) {
+        warn!("failed to log out: {}", e);
+      }
+    }
+    self.free_user(user);
+    if let Root { users } = &mut self.inos[parent as usize] { users.retain(|x| x.1 != user); }
+    reply.ok();
+  }
+
+  // called when removing a file, now only useful for removing discussion replies
+  fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    info!("unlink parent={} name={:?}", parent, name);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), parent) { return reply.error(EACCES); }
+    let name = name.to_string_lossy();
+    match &mut self.inos[parent as usize] {
+      Discussion { replies, .. } => {
+        if let Some(idx) = do_lookup(replies, &name) {
+          match &self.inos[idx as usize] {
+            DiscussionReply { course_discussion, id, client, .. } => {
+              let id = if let Some(x) = id.as_ref() { x } else { return reply.error(EPERM); };
+              let _ = unwrap!(self.runtime.request(client.delete_discussion_reply(&course_discussion.0, id)), reply);
+              reply.ok();
+            }
+            // `Refresh` or the `.html` file of a reply
+            _ => reply.error(EPERM),
+          }
+        } else { reply.error(ENOENT); }
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+  // download the url when this file is Content::Url, nop (but not an error) for other files
+  fn open(&mut self, req: &Request, ino: u64, _flags: u32, reply: ReplyOpen) {
+    info!("open ino={}", ino);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    if let Err(e) = self.load(ino) { return reply.error(e); }
+    if let Archive { readers, .. } | SemesterGrades { readers, .. } = &mut self.inos[ino as usize] { *readers += 1; }
+    // this is the default implementation of `FileSystem`, returning such data makes the `open` operation useless
+    reply.opened(0, 0);
+  }
+
+  fn read(&mut self, req: &Request, ino: u64, _fh: u64, offset: i64, size: u32, reply: ReplyData) {
+    info!("read ino={} offset={} size={}", ino, offset, size);
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    let reply_bytes = move |b: &[u8], reply: ReplyData| {
+      // reading past the end gives no data, the file may also have been regenerated shorter since it was opened
+      let b = b.get(offset as usize..).unwrap_or_default();
+      reply.data(&b[..b.len().min(size as usize)])
+    };
+    match self.data(ino) {
+      Some(b) => reply_bytes(b, reply),
+      None => reply.error(EPERM),
+    }
+  }
+
+  fn write(&mut self, req: &Request, ino: u64, _fh: u64, _offset: i64, data: &[u8], _flags: u32, reply: ReplyWrite) {
+    info!("write ino={} data={:?}", ino, data);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    // `FILE=<path> <content>` attaches the file at `<path>`, which is relative to the cwd of the writer
+    fn parse_data(data: &[u8], pid: u32) -> Result<(String, Option<(&str, Vec<u8>)>), c_int> {
+      let data = std::str::from_utf8(&data).map_err(|_| EINVAL)?;
+      let (file, content) = if data.starts_with("FILE=") {
+        let data = &data[5..];
+        let file_end = data.find(|x: char| x.is_whitespace()).unwrap_or(data.len());
+        (Some(&data[..file_end]), &data[file_end..])
+      } else { (None, data) };
+      let file = match file {
+        Some(f) => Some((f, read_file(pid, f).map_err(|e| e.raw_os_error().unwrap_or(EIO))?)),
+        None => None,
+      };
+      Ok((content.to_owned(), file))
+    }
+    // the operation of fuse is not re-entrant, so submitting and replying block the whole filesystem until they finish
+    // in return, their failures reach the writer, and they can still be interrupted
+    match &self.inos[ino as usize] {
+      SubmitHomework { .. } => {
+        let (content, file) = match parse_data(data, req.pid()) { Ok(x) => x, Err(e) => return reply.error(e) };
+        match self.submit(ino, content, file) { Ok(()) => reply.written(data.len() as u32), Err(e) => reply.error(e) }
+      }
+      Refresh { .. } => match self.refresh(ino) {
+        Ok(()) => reply.written(data.len() as u32),
+        Err(e) => reply.error(e),
+      }
+      ReadState { .. } => {
+        let read = match std::str::from_utf8(data).map(str::trim) {
+          Ok(x) if x == self.names.yes => true,
+          Ok(x) if x == self.names.no => false,
+          _ => return reply.error(EINVAL),
+        };
+        match self.set_read(ino, read) { Ok(()) => reply.written(data.len() as u32), Err(e) => reply.error(e) }
+      }
+      DiscussionReply { .. } => {
+        let (content, file) = match parse_data(data, req.pid()) { Ok(x) => x, Err(e) => return reply.error(e) };
+        match self.reply_discussion(ino, None, content, file) { Ok(()) => reply.written(data.len() as u32), Err(e) => reply.error(e) }
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+  fn release(&mut self, _req: &Request, ino: u64, _fh: u64, _flags: u32, _lock_owner: u64, _flush: bool, reply: ReplyEmpty) {
+    info!("release ino={}", ino);
+    if let Archive { readers, .. } | SemesterGrades { readers, .. } = &mut self.inos[ino as usize] { *readers = readers.saturating_sub(1); }
+    self.unload(ino);
+    reply.ok();
+  }
+
+  // we never need flush or sync
+  fn flush(&mut self, _req: &Request, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) { reply.ok(); }
+
+  fn fsync(&mut self, _req: &Request, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) { reply.ok(); }
+
+  // if all contents are represented as an array a, we must return a[offset..]
+  fn readdir(&mut self, req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+    info!("readdir ino={} offset={}", ino, offset);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    if offset < 1 { reply.add(ino, 1, Directory, "."); }
+    if offset < 2 { reply.add(ino, 2, Directory, ".."); }
+    fn reply_map<S: Borrow<str>>(m: impl IntoIterator<Item=impl Borrow<(S, u64)>>, offset: i64, kind: impl Fn(u64) -> FileType, mut reply: ReplyDirectory) {
+      for (idx, x) in m.into_iter().enumerate().skip((offset - 2).max(0) as usize) {
+        let (id, ino) = x.borrow();
+        reply.add(*ino, (idx + 3) as i64, kind(*ino), id.borrow());
+      }
+      reply.ok();
+    }
+    let kind = |ino| self.attr(ino).kind;
+    match &self.inos[ino as usize] {
+      Root { users: m } | User { semesters: m, .. } => reply_map(m, offset, kind, reply),
+      Semester { .. } => {
+        if let Err(e) = self.fetch_courses(ino) { return reply.error(e); }
+        match &self.inos[ino as usize] {
+          Semester { courses, .. } => reply_map(courses, offset, |ino| self.attr(ino).kind, reply),
+          _ => reply.error(EIO),
+        }
+      }
+      ItemList(m, idx) => reply_map(self.list_view(m, *idx), offset, kind, reply),
+      Item(m, _) => reply_map(m, offset, kind, reply),
+      Course { .. } => reply_map(course_children(self.names, ino), offset, kind, reply),
+      Discussion { .. } => {
+        if let Err(e) = self.fetch_discussion_replies(ino) { return reply.error(e); }
+        match &self.inos[ino as usize] {
+          Discussion { replies, .. } => reply_map(replies, offset, |ino| self.attr(ino).kind, reply),
+          _ => reply.error(EIO),
+        }
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+  fn create(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, _mode: u32, _flags: u32, reply: ReplyCreate) {
+    info!("create parent={} name={:?}", parent, name);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), parent) { return reply.error(EACCES); }
+    let name = name.to_string_lossy();
+    match &mut self.inos[parent as usize] {
+      Item(m, _) =>
+        if let Some(ino) = do_lookup(m, &name) {
+          match &self.inos[ino as usize] {
+            SubmitHomework { .. } | Refresh { .. } | ReadState { .. } => reply.created(&TTL, &self.attr(ino), 0, 0, 0),
+            _ => reply.error(EPERM),
+          }
+        } else { reply.error(EPERM); }
+      Discussion { .. } => {
+        if let Err(e) = self.fetch_discussion_replies(parent) { return reply.error(e); }
+        match &mut self.inos[parent as usize] {
+          Discussion { replies: m, .. } =>
+            if let Some(ino) = do_lookup(m, &name) {
+              reply.created(&TTL, &self.attr(ino), 0, 0, 0);
+            } else { reply.error(EPERM); }
+          _ => reply.error(EIO),
+        }
+      }
+      _ => reply.error(EPERM),
+    }
+  }
+
+  fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+    info!("getxattr ino={} name={:?} size={}", ino, name, size);
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    let name = name.to_string_lossy();
+    if let Some((_, val)) = self.xattrs(ino).into_iter().find(|(k, _)| XATTR_PREFIX.to_owned() + k == name) {
+      reply_xattr(val.as_bytes(), size, reply);
+    } else { reply.error(ENODATA); }
+  }
+
+  fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+    info!("listxattr ino={} size={}", ino, size);
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    // the list is a sequence of null-terminated names
+    let mut names = Vec::new();
+    for (k, _) in self.xattrs(ino) {
+      names.extend_from_slice(XATTR_PREFIX.as_bytes());
+      names.extend_from_slice(k.as_bytes());
+      names.push(0);
+    }
+    reply_xattr(&names, size, reply);
+  }
+
+  // only `user.learn.read` of a notification can be set, its value should be `true` or `false`
+  fn setxattr(&mut self, req: &Request, ino: u64, name: &OsStr, value: &[u8], _flags: u32, _position: u32, reply: ReplyEmpty) {
+    info!("setxattr ino={} name={:?} value={:?}", ino, name, value);
+    self.runtime.caller = Some(req.pid());
+    if !self.accessible(req.uid(), ino) { return reply.error(EACCES); }
+    if name.to_string_lossy() != XATTR_PREFIX.to_owned() + "read" { return reply.error(EPERM); }
+    let read = match value { b"true" => true, b"false" => false, _ => return reply.error(EINVAL) };
+    match self.set_item_read(ino, read) { Ok(()) => reply.ok(), Err(e) => reply.error(e) }
+  }
+}
+
+// `LearnFS` shared with the JSON API server in another thread, each operation locks it
+pub(crate) struct SharedFS<B = LearnHelper>(pub(crate) Arc<Mutex<LearnFS<B>>>);
+
+impl<B: Backend> Filesystem for SharedFS<B> {
+  fn lookup(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
+    self.0.lock().unwrap().lookup(req, parent, name, reply)
+  }
+
+  fn getattr(&mut self, req: &Request, ino: u64, reply: ReplyAttr) {
+    self.0.lock().unwrap().getattr(req, ino, reply)
+  }
+
+  fn setattr(&mut self, req: &Request, ino: u64, mode: Option<u32>, uid: Option<u32>, gid: Option<u32>, size: Option<u64>, atime: Option<SystemTime>, mtime: Option<SystemTime>, fh: Option<u64>, crtime: Option<SystemTime>, chgtime: Option<SystemTime>, bkuptime: Option<SystemTime>, flags: Option<u32>, reply: ReplyAttr) {
+    self.0.lock().unwrap().setattr(req, ino, mode, uid, gid, size, atime, mtime, fh, crtime, chgtime, bkuptime, flags, reply)
+  }
+
+  fn mkdir(&mut self, req: &Request, parent: u64, name: &OsStr, mode: u32, reply: ReplyEntry) {
+    self.0.lock().unwrap().mkdir(req, parent, name, mode, reply)
+  }
+
+  fn rmdir(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    self.0.lock().unwrap().rmdir(req, parent, name, reply)
+  }
+
+  fn unlink(&mut self, req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+    self.0.lock().unwrap().unlink(req, parent, name, reply)
+  }
+
+  fn open(&mut self, req: &Request, ino: u64, flags: u32, reply: ReplyOpen) {
+    self.0.lock().unwrap().open(req, ino, flags, reply)
+  }
+
+  fn release(&mut self, req: &Request, ino: u64, fh: u64, flags: u32, lock_owner: u64, flush: bool, reply: ReplyEmpty) {
+    self.0.lock().unwrap().release(req, ino, fh, flags, lock_owner, flush, reply)
+  }
+
+  fn read(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, size: u32, reply: ReplyData) {
+    self.0.lock().unwrap().read(req, ino, fh, offset, size, reply)
+  }
+
+  fn write(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, data: &[u8], flags: u32, reply: ReplyWrite) {
+    self.0.lock().unwrap().write(req, ino, fh, offset, data, flags, reply)
+  }
+
+  fn flush(&mut self, req: &Request, ino: u64, fh: u64, lock_owner: u64, reply: ReplyEmpty) {
+    self.0.lock().unwrap().flush(req, ino, fh, lock_owner, reply)
+  }
+
+  fn fsync(&mut self, req: &Request, ino: u64, fh: u64, datasync: bool, reply: ReplyEmpty) {
+    self.0.lock().unwrap().fsync(req, ino, fh, datasync, reply)
+  }
+
+  fn readdir(&mut self, req: &Request, ino: u64, fh: u64, offset: i64, reply: ReplyDirectory) {
+    self.0.lock().unwrap().readdir(req, ino, fh, offset, reply)
+  }
+
+  fn create(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, mode: u32, flags: u32, reply: ReplyCreate) {
+    self.0.lock().unwrap().create(req, parent, name, mode, flags, reply)
+  }
+
+  fn getxattr(&mut self, req: &Request, ino: u64, name: &OsStr, size: u32, reply: ReplyXattr) {
+    self.0.lock().unwrap().getxattr(req, ino, name, size, reply)
+  }
+
+  fn listxattr(&mut self, req: &Request, ino: u64, size: u32, reply: ReplyXattr) {
+    self.0.lock().unwrap().listxattr(req, ino, size, reply)
+  }
+
+  fn setxattr(&mut self, req: &Request, ino: u64, name: &OsStr, value: &[u8], flags: u32, position: u32, reply: ReplyEmpty) {
+    self.0.lock().unwrap().setxattr(req, ino, name, value, flags, position, reply)
+  }
+}
+
+// `size == 0` means the caller is asking for the length of the value, otherwise the value must fit in `size`
+fn reply_xattr(data: &[u8], size: u32, reply: ReplyXattr) {
+  if size == 0 { reply.size(data.len() as u32); } else if data.len() <= size as usize { reply.data(data); } else { reply.error(ERANGE); }
+}
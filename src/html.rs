@@ -0,0 +1,209 @@
+use serde::Deserialize;
+
+// the format of homework descriptions, notification contents and discussion replies, which are html from the server
+// for `Text` and `Markdown`, the original html is kept in a sibling file with suffix `.html`
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyFormat { Text, Markdown, Html }
+
+impl Default for BodyFormat {
+  fn default() -> Self { BodyFormat::Text }
+}
+
+// it is not a complete html parser, but enough for the html produced by the rich text editor of the web learning
+pub fn convert(html: &str, format: BodyFormat) -> String {
+  if format == BodyFormat::Html { return html.to_owned(); }
+  let mut r = Renderer { out: String::new(), markdown: format == BodyFormat::Markdown, space: false, links: Vec::new(), lists: Vec::new() };
+  let mut rest = html;
+  while !rest.is_empty() {
+    if rest.starts_with("<!--") {
+      rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+      continue;
+    }
+    if rest.starts_with('<') {
+      if let Some(end) = rest.find('>') {
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/').trim_end_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        let (name, attrs) = (tag[..name_end].to_ascii_lowercase(), &tag[name_end..]);
+        if !closing && (name == "script" || name == "style") {
+          // the content of them is not text, skip to the closing tag
+          rest = rest.to_ascii_lowercase().find(&format!("</{}", name)).map_or("", |end| &rest[end..]);
+        } else {
+          r.tag(&name, attrs, closing);
+        }
+        continue;
+      }
+    }
+    // a `<` without matching `>` is treated as text
+    let first = rest.chars().next().map_or(0, char::len_utf8);
+    let end = rest[first..].find('<').map_or(rest.len(), |end| end + first);
+    r.text(&decode_entities(&rest[..end]));
+    rest = &rest[end..];
+  }
+  let out = r.out.trim();
+  if out.is_empty() { String::new() } else { out.to_owned() + "\n" }
+}
+
+const MARKDOWN_SPECIAL: &str = "\\*_[]`";
+
+struct Renderer {
+  out: String,
+  markdown: bool,
+  // there are whitespaces not written to `out` yet, consecutive whitespaces are collapsed into one
+  space: bool,
+  // the href of each `<a>` being rendered, and the position in `out` where its text starts
+  links: Vec<(Option<String>, usize)>,
+  // the next index of each `<ol>` being rendered, `None` for `<ul>`
+  lists: Vec<Option<usize>>,
+}
+
+impl Renderer {
+  fn at_line_start(&self) -> bool { self.out.is_empty() || self.out.ends_with('\n') }
+
+  // in Markdown, characters which would start emphasis, a link or code are escaped, so that text is never taken as markup
+  // others (e.g. `#` or `1.` at the start of a line) are rare in the middle of html text, and left as they are
+  fn text(&mut self, s: &str) {
+    for c in s.chars() {
+      if c.is_whitespace() { self.space = true; } else {
+        self.flush_space();
+        if self.markdown && MARKDOWN_SPECIAL.contains(c) { self.out.push('\\'); }
+        self.out.push(c);
+      }
+    }
+  }
+
+  fn flush_space(&mut self) {
+    if self.space && !self.at_line_start() && !self.out.ends_with(' ') { self.out.push(' '); }
+    self.space = false;
+  }
+
+  // make `out` end with at least `n` newlines, unless it is empty
+  fn newline(&mut self, n: usize) {
+    self.space = false;
+    if self.out.is_empty() { return; }
+    self.out.truncate(self.out.trim_end_matches(' ').len());
+    let have = self.out.len() - self.out.trim_end_matches('\n').len();
+    for _ in have..n { self.out.push('\n'); }
+  }
+
+  fn tag(&mut self, name: &str, attrs: &str, closing: bool) {
+    match name {
+      "br" => {
+        self.out.truncate(self.out.trim_end_matches(' ').len());
+        self.out.push('\n');
+        self.space = false;
+      }
+      "p" | "table" | "blockquote" | "pre" => self.newline(2),
+      "div" | "tr" | "section" => self.newline(1),
+      "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+        self.newline(2);
+        if !closing && self.markdown {
+          for _ in 0..name[1..].parse::<usize>().unwrap_or(1) { self.out.push('#'); }
+          self.out.push(' ');
+        }
+      }
+      "hr" => {
+        self.newline(2);
+        self.out.push_str("---");
+        self.newline(2);
+      }
+      "ul" | "ol" => {
+        if closing { self.lists.pop(); } else { self.lists.push(if name == "ol" { Some(1) } else { None }); }
+        self.newline(1);
+      }
+      "li" if !closing => {
+        self.newline(1);
+        for _ in 1..self.lists.len() { self.out.push_str("  "); }
+        match self.lists.last_mut() {
+          Some(Some(idx)) => {
+            self.out.push_str(&format!("{}. ", idx));
+            *idx += 1;
+          }
+          _ => self.out.push_str("- "),
+        }
+      }
+      "td" | "th" if !closing && !self.at_line_start() => self.out.push_str(" | "),
+      "b" | "strong" if self.markdown => self.inline("**", closing),
+      "i" | "em" if self.markdown => self.inline("*", closing),
+      "a" if !closing => {
+        // a link to javascript is not useful outside the browser
+        let href = attr(attrs, "href").filter(|x| !x.is_empty() && !x.starts_with("javascript:"));
+        if href.is_some() && self.markdown { self.inline("[", false); } else { self.flush_space(); }
+        self.links.push((href, self.out.len()));
+      }
+      "a" => if let Some((Some(href), start)) = self.links.pop() {
+        if self.markdown {
+          self.out.push_str(&format!("]({})", href));
+        } else if self.out.get(start..).map_or(true, |text| text.trim() != href) {
+          self.out.push_str(&format!(" ({})", href));
+        }
+      }
+      "img" => if let Some(src) = attr(attrs, "src") {
+        let alt = attr(attrs, "alt").unwrap_or_default();
+        self.flush_space();
+        self.out.push_str(&if self.markdown { format!("![{}]({})", alt, src) } else { format!("[{}]({})", alt, src) });
+      }
+      _ => {}
+    }
+  }
+
+  // the pending whitespace is written before an opening mark, and after a closing mark
+  fn inline(&mut self, mark: &str, closing: bool) {
+    if !closing { self.flush_space(); }
+    self.out.push_str(mark);
+  }
+}
+
+// value of attribute `name` in `attrs`, which is the part of a tag after the tag name
+fn attr(attrs: &str, name: &str) -> Option<String> {
+  let lower = attrs.to_ascii_lowercase();
+  let mut from = 0;
+  while let Some(pos) = lower[from..].find(name) {
+    let pos = from + pos;
+    from = pos + name.len();
+    // it must be a whole attribute name, instead of part of another attribute
+    if pos > 0 && !lower.as_bytes()[pos - 1].is_ascii_whitespace() { continue; }
+    let rest = attrs[from..].trim_start();
+    if !rest.starts_with('=') { continue; }
+    let rest = rest[1..].trim_start();
+    let value = match rest.chars().next() {
+      Some(q) if q == '"' || q == '\'' => rest[1..].split(q).next().unwrap_or(""),
+      _ => rest.split(char::is_whitespace).next().unwrap_or(""),
+    };
+    return Some(decode_entities(value));
+  }
+  None
+}
+
+pub fn decode_entities(s: &str) -> String {
+  let (mut ret, mut rest) = (String::with_capacity(s.len()), s);
+  while let Some(pos) = rest.find('&') {
+    ret.push_str(&rest[..pos]);
+    rest = &rest[pos..];
+    // the longest entity we recognize is less than 10 bytes
+    let end = rest.find(';').filter(|&end| end <= 10);
+    let c = end.and_then(|end| match &rest[1..end] {
+      "nbsp" => Some(' '), "amp" => Some('&'), "lt" => Some('<'), "gt" => Some('>'), "quot" => Some('"'), "apos" => Some('\''),
+      "ldquo" => Some('“'), "rdquo" => Some('”'), "lsquo" => Some('‘'), "rsquo" => Some('’'), "hellip" => Some('…'),
+      "mdash" => Some('—'), "ndash" => Some('–'), "middot" => Some('·'), "times" => Some('×'),
+      e if e.starts_with("#x") || e.starts_with("#X") => u32::from_str_radix(&e[2..], 16).ok().and_then(std::char::from_u32),
+      e if e.starts_with('#') => e[1..].parse().ok().and_then(std::char::from_u32),
+      _ => None,
+    });
+    match (c, end) {
+      (Some(c), Some(end)) => {
+        ret.push(c);
+        rest = &rest[end + 1..];
+      }
+      _ => {
+        ret.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  ret.push_str(rest);
+  ret
+}
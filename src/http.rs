@@ -0,0 +1,152 @@
+use std::{fs, io::{self, BufRead, BufReader, Read, Write}, net::TcpListener, path::Path};
+use std::os::unix::{fs::{FileTypeExt, PermissionsExt}, io::AsRawFd, net::{UnixListener, UnixStream}};
+
+// a minimal HTTP/1.1 server for `webdav` and `api`
+// requests are handled one by one because `LearnFS` is not thread-safe, and every response closes the connection
+// `serve` is bound to localhost, where any local user can connect, so every request must carry `password` by HTTP Basic auth
+pub fn serve(port: u16, password: &str, mut handler: impl FnMut(&Request) -> Response) -> io::Result<()> {
+  let listener = TcpListener::bind(("127.0.0.1", port))?;
+  for stream in listener.incoming() {
+    let handled = stream.and_then(|s| handle(s, &mut |req: &Request| {
+      if authorized(req, password) { return handler(req); }
+      ("401 Unauthorized", vec![("WWW-Authenticate", "Basic realm=\"learn\", charset=\"UTF-8\"".to_owned())], Vec::new())
+    }));
+    if let Err(e) = handled { warn!("http: {}", e); }
+  }
+  Ok(())
+}
+
+// the user name of HTTP Basic auth for `serve`
+pub const USER: &str = "learn";
+
+// whether `req` has `Authorization: Basic <base64 of "learn:<password>">`, compared in constant time
+pub(crate) fn authorized(req: &Request, password: &str) -> bool {
+  let expected = format!("Basic {}", base64(format!("{}:{}", USER, password).as_bytes()));
+  match &req.authorization {
+    Some(x) if x.len() == expected.len() => x.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0,
+    _ => false,
+  }
+}
+
+pub fn base64(data: &[u8]) -> String {
+  const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let mut ret = String::with_capacity((data.len() + 2) / 3 * 4);
+  for chunk in data.chunks(3) {
+    let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+    let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+    for i in 0..4 {
+      ret.push(if i <= chunk.len() { TABLE[n >> (18 - 6 * i) & 63] as char } else { '=' });
+    }
+  }
+  ret
+}
+
+// like `serve`, on a unix socket at `path` which any local user can connect to, the handler also gets the uid of the peer
+pub fn serve_unix(path: &Path, mut handler: impl FnMut(&Request, u32) -> Response) -> io::Result<()> {
+  // a socket left by a previous run makes binding fail, anything else at `path` is kept
+  if fs::symlink_metadata(path).map_or(false, |x| x.file_type().is_socket()) { fs::remove_file(path)?; }
+  let listener = UnixListener::bind(path)?;
+  fs::set_permissions(path, fs::Permissions::from_mode(0o666))?;
+  for stream in listener.incoming() {
+    let handled = stream.and_then(|s| {
+      let uid = peer_uid(&s)?;
+      handle(s, &mut |req: &Request| handler(req, uid))
+    });
+    if let Err(e) = handled { warn!("http: {}", e); }
+  }
+  Ok(())
+}
+
+// the uid of the process on the other side, checked by the kernel
+fn peer_uid(s: &UnixStream) -> io::Result<u32> {
+  let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+  let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+  let ret = unsafe { libc::getsockopt(s.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, &mut cred as *mut _ as *mut libc::c_void, &mut len) };
+  if ret == 0 { Ok(cred.uid) } else { Err(io::Error::last_os_error()) }
+}
+
+pub struct Request {
+  pub method: String,
+  // percent-decoded, without the query string
+  pub path: String,
+  pub depth: Option<String>,
+  pub authorization: Option<String>,
+  pub body: Vec<u8>,
+}
+
+// status, extra headers, body
+pub type Response = (&'static str, Vec<(&'static str, String)>, Vec<u8>);
+
+// the largest body accepted, which is read into memory before handling
+// bodies are only the content of a submission or JSON, attachments are never uploaded through them
+pub const MAX_BODY: usize = 16 << 20;
+
+// a request which can't be handled is the status to reply with
+pub(crate) fn read_request<S: Read + Write>(r: &mut BufReader<S>) -> io::Result<Result<Request, &'static str>> {
+  let mut line = String::new();
+  r.read_line(&mut line)?;
+  let mut parts = line.split_whitespace();
+  let (method, target) = (parts.next().unwrap_or("").to_owned(), parts.next().unwrap_or("/"));
+  let path = percent_decode(target.split('?').next().unwrap_or(""));
+  let (mut len, mut depth, mut authorization, mut expect, mut chunked) = (0, None, None, false, false);
+  loop {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 { break; }
+    let line = line.trim_end();
+    if line.is_empty() { break; }
+    if let Some(colon) = line.find(':') {
+      let (k, v) = (line[..colon].trim().to_ascii_lowercase(), line[colon + 1..].trim());
+      match k.as_str() {
+        "content-length" => len = v.parse().unwrap_or(0),
+        "depth" => depth = Some(v.to_owned()),
+        "authorization" => authorization = Some(v.to_owned()),
+        "expect" => expect = v.eq_ignore_ascii_case("100-continue"),
+        "transfer-encoding" => chunked = !v.eq_ignore_ascii_case("identity"),
+        _ => {}
+      }
+    }
+  }
+  // chunked bodies are not supported
+  if chunked { return Ok(Err("411 Length Required")); }
+  // checked before `100 Continue`, so that the client doesn't send it at all
+  if len > MAX_BODY { return Ok(Err("413 Payload Too Large")); }
+  if expect && len > 0 { r.get_mut().write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?; }
+  let mut body = vec![0; len];
+  r.read_exact(&mut body)?;
+  Ok(Ok(Request { method, path, depth, authorization, body }))
+}
+
+fn handle<S: Read + Write>(stream: S, handler: &mut impl FnMut(&Request) -> Response) -> io::Result<()> {
+  let mut r = BufReader::new(stream);
+  let req = read_request(&mut r)?;
+  let (status, headers, body) = match &req {
+    Ok(req) => handler(req),
+    Err(status) => (*status, Vec::new(), Vec::new()),
+  };
+  info!("http {:?} {:?} -> {}", req.as_ref().ok().map(|x| &x.method), req.as_ref().ok().map(|x| &x.path), status);
+  let mut w = r.into_inner();
+  write!(w, "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len())?;
+  for (k, v) in headers { write!(w, "{}: {}\r\n", k, v)?; }
+  w.write_all(b"\r\n")?;
+  if req.map_or(true, |x| x.method != "HEAD") { w.write_all(&body)?; }
+  w.flush()
+}
+
+fn percent_decode(s: &str) -> String {
+  let (s, mut ret) = (s.as_bytes(), Vec::with_capacity(s.len()));
+  let mut i = 0;
+  while i < s.len() {
+    let hex = s.get(i + 1..i + 3).and_then(|x| std::str::from_utf8(x).ok()).and_then(|x| u8::from_str_radix(x, 16).ok());
+    match (s[i], hex) {
+      (b'%', Some(b)) => {
+        ret.push(b);
+        i += 3;
+      }
+      (b, _) => {
+        ret.push(b);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&ret).into_owned()
+}
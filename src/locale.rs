@@ -0,0 +1,167 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale { Zh, En }
+
+impl Default for Locale {
+  fn default() -> Self { Locale::Zh }
+}
+
+impl Locale {
+  pub fn names(self) -> &'static Names {
+    match self { Locale::Zh => &ZH, Locale::En => &EN }
+  }
+}
+
+// all fixed names of virtual files and directories, and other text shown to the user in the filesystem
+// `xxx_attachment` are prefixes of the attachment names
+pub struct Names {
+  // homework, notification, file, discussion
+  pub course_content: [&'static str; 4],
+  // the course information file, in text and in json
+  pub course_info: &'static str,
+  pub course_info_json: &'static str,
+  // the grade summary file of a course or a semester
+  pub grade_summary: &'static str,
+  // the zip of all attachments in a course or a category
+  pub archive: &'static str,
+  // suffixes of semester ids ending with 1, 2, 3
+  pub semester: [&'static str; 3],
+  pub password_prompt: &'static str,
+  pub yes: &'static str,
+  pub no: &'static str,
+  pub submit: &'static str,
+  pub refresh: &'static str,
+  pub read: &'static str,
+  pub title: &'static str,
+  pub description: &'static str,
+  pub assign_time: &'static str,
+  pub deadline: &'static str,
+  pub submit_time: &'static str,
+  pub submit_content: &'static str,
+  pub grade: &'static str,
+  pub grade_time: &'static str,
+  pub grader: &'static str,
+  pub grade_content: &'static str,
+  pub attachment: &'static str,
+  pub submit_attachment: &'static str,
+  pub grade_attachment: &'static str,
+  pub content: &'static str,
+  pub publish_time: &'static str,
+  pub publisher: &'static str,
+  pub important: &'static str,
+  pub notification_attachment: &'static str,
+  pub size: &'static str,
+  pub upload_time: &'static str,
+  pub visit_count: &'static str,
+  pub download_count: &'static str,
+  // labels of lines in `course_info`
+  pub course_name: &'static str,
+  pub english_name: &'static str,
+  pub course_number: &'static str,
+  pub course_index: &'static str,
+  pub teacher: &'static str,
+  pub teacher_number: &'static str,
+  pub time_location: &'static str,
+  // default templates of `config::Naming::reply` and `config::Naming::sub_reply`
+  pub reply: &'static str,
+  pub sub_reply: &'static str,
+}
+
+impl Names {
+  pub fn bool2str(&self, b: bool) -> &'static str { if b { self.yes } else { self.no } }
+}
+
+pub const ZH: Names = Names {
+  course_content: ["作业", "通知", "文件", "讨论"],
+  course_info: "课程信息",
+  course_info_json: "课程信息.json",
+  grade_summary: "成绩汇总.csv",
+  archive: "全部文件.zip",
+  semester: ["秋", "春", "夏"],
+  password_prompt: "请输入密码：",
+  yes: "是",
+  no: "否",
+  submit: "提交作业",
+  refresh: "刷新",
+  read: "已读",
+  title: "标题",
+  description: "描述",
+  assign_time: "发布时间",
+  deadline: "截止时间",
+  submit_time: "提交时间",
+  submit_content: "提交内容",
+  grade: "成绩",
+  grade_time: "批阅时间",
+  grader: "批阅老师",
+  grade_content: "评语",
+  attachment: "附件：",
+  submit_attachment: "提交附件：",
+  grade_attachment: "评语附件：",
+  content: "内容",
+  publish_time: "发布时间",
+  publisher: "发布老师",
+  important: "重要",
+  notification_attachment: "通知附件：",
+  size: "大小",
+  upload_time: "上传时间",
+  visit_count: "访问次数",
+  download_count: "下载次数",
+  course_name: "课程名",
+  english_name: "英文名",
+  course_number: "课程号",
+  course_index: "课序号",
+  teacher: "教师",
+  teacher_number: "教师号",
+  time_location: "上课时间地点",
+  reply: "{floor}楼-{author}-{time}",
+  sub_reply: "{floor}楼-回复{reply}-{author}-{time}",
+};
+
+// only ASCII characters are used, and names contain no space
+pub const EN: Names = Names {
+  course_content: ["homework", "notification", "file", "discussion"],
+  course_info: "course_info",
+  course_info_json: "course_info.json",
+  grade_summary: "grade_summary.csv",
+  archive: "all_files.zip",
+  semester: ["fall", "spring", "summer"],
+  password_prompt: "Password: ",
+  yes: "yes",
+  no: "no",
+  submit: "submit",
+  refresh: "refresh",
+  read: "read",
+  title: "title",
+  description: "description",
+  assign_time: "assign_time",
+  deadline: "deadline",
+  submit_time: "submit_time",
+  submit_content: "submit_content",
+  grade: "grade",
+  grade_time: "grade_time",
+  grader: "grader",
+  grade_content: "comment",
+  attachment: "attachment-",
+  submit_attachment: "submit_attachment-",
+  grade_attachment: "comment_attachment-",
+  content: "content",
+  publish_time: "publish_time",
+  publisher: "publisher",
+  important: "important",
+  notification_attachment: "attachment-",
+  size: "size",
+  upload_time: "upload_time",
+  visit_count: "visit_count",
+  download_count: "download_count",
+  course_name: "name",
+  english_name: "english_name",
+  course_number: "course_number",
+  course_index: "course_index",
+  teacher: "teacher",
+  teacher_number: "teacher_number",
+  time_location: "time_location",
+  reply: "{floor}-{author}-{time:%Y%m%d%H%M}",
+  sub_reply: "{floor}-reply{reply}-{author}-{time:%Y%m%d%H%M}",
+};
@@ -1,15 +1,35 @@
-#![feature(async_closure)]
 #[macro_use]
 extern crate log;
 
-use fuse::{Filesystem, Request, ReplyEntry, ReplyAttr, ReplyDirectory, FileType::*, FileAttr, ReplyData, ReplyWrite, ReplyOpen, ReplyEmpty, ReplyCreate};
-use libc::{ENOENT, EIO, EPERM};
-use tokio::runtime::Runtime;
-use futures::future::{try_join_all, try_join4};
+mod config;
+mod template;
+mod locale;
+mod html;
+mod sync;
+mod cli;
+mod fuse_fs;
+mod http;
+mod webdav;
+mod api;
+mod backend;
+mod fixture;
+#[cfg(test)]
+mod tests;
+
+use fuse::{FileType::{self, *}, FileAttr};
+use libc::{c_int, ENOENT, EIO, EPERM, EACCES, EINVAL, ENODATA, ERANGE, ENOTDIR, ENOTSUP};
+use futures::{future::{try_join4, try_join_all}, stream::{self, StreamExt}};
 use bytes::Bytes;
 use openat::Dir;
-use std::{ffi::OsStr, time::{Duration, UNIX_EPOCH}, sync::Arc, borrow::{Borrow, Cow}, time::SystemTime};
-use thu_learn_helper::{LearnHelper, types::{Homework, HomeworkDetail, Notification, File, Error}};
+use serde_json::{json, Value};
+use chrono::NaiveDateTime;
+use std::{ffi::{OsStr, OsString}, time::{Duration, UNIX_EPOCH}, sync::{Arc, Mutex}, borrow::{Borrow, Cow}, time::SystemTime};
+use thu_learn_helper::{LearnHelper, types::{Homework, HomeworkDetail, Notification, File}};
+use backend::{Backend, BoxError, Executor, errno};
+use config::{Config, SortBy};
+use template::{render, Var};
+use locale::Names;
+use html::BodyFormat;
 
 use InoInfo::*;
 
@@ -21,103 +41,328 @@ fn do_lookup<S: Borrow<str>>(m: impl IntoIterator<Item=impl Borrow<(S, u64)>>, k
   m.into_iter().find(|x| x.borrow().0.borrow() == k).map(|x| x.borrow().1)
 }
 
+// the longest file name in bytes, a longer one fails with `ENAMETOOLONG` when copied out of the mount
+const NAME_MAX: usize = 255;
+
+// the longest prefix of `s` with at most `max` bytes, which doesn't split a character
+fn truncate(s: &str, max: usize) -> &str {
+  let end = (0..=max.min(s.len())).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0);
+  &s[..end]
+}
+
+// names from the server may be illegal as a filename, or duplicate with existing names in `m`
+// `/` is replaced with a full-width `／`, NUL is removed, a name longer than `NAME_MAX` is truncated,
+// and duplicated names get a suffix ` (2)`, ` (3)`, ..., which is kept within `NAME_MAX` by truncating the name before it
+// the suffix only depends on the order of the server's response, so it is stable across refreshing
+// all names are pushed to a Map by it, so that `lookup` and `readdir` always agree with each other
+fn push_unique<S: Borrow<str> + From<String>>(m: &mut Vec<(S, u64)>, name: &str, ino: u64) {
+  let mut name = truncate(&name.replace('/', "／").replace('\0', ""), NAME_MAX).to_owned();
+  if name.is_empty() || name == "." || name == ".." { name.insert(0, '_'); }
+  if do_lookup(m.iter(), &name).is_some() {
+    name = (2..).map(|i| {
+      let suffix = format!(" ({})", i);
+      truncate(&name, NAME_MAX - suffix.len()).to_owned() + &suffix
+    }).find(|x| do_lookup(m.iter(), x).is_none()).unwrap();
+  }
+  m.push((name.into(), ino));
+}
+
 // all Map's value(u64) are pointer to other ino
-enum InoInfo {
+enum InoInfo<B> {
   Root { users: Map },
   // the Map key in parent variant is human-readable, and children variant may store their api-used name
-  User { semesters: Map },
-  Semester { courses: Map },
+  User {
+    semesters: Map,
+    // the local user who ran `mkdir <student id>`
+    uid: u32,
+    client: Arc<B>,
+  },
+  // courses are fetched on first access, because a senior student may have many old semesters
+  Semester {
+    id: String,
+    client: Arc<B>,
+    courses: Map,
+    fetched: bool,
+  },
+  // `成绩汇总.csv` of a semester, it is regenerated from all courses in the semester on every `open` while nobody else has it open
+  SemesterGrades {
+    semester: u64,
+    content: Bytes,
+    // processes having it open in the mount, like `Archive::readers`
+    readers: u32,
+  },
   Course {
     id: Arc<String>,
-    client: Arc<LearnHelper>,
+    client: Arc<B>,
     fetched: bool,
   },
+  // `全部文件.zip` of a `Course` or an `ItemList`, it is generated in memory from all attachments in `root` on `open`
+  // a zip can't be produced lazily on `read`, because its size and the offsets in it depend on the sizes of attachments,
+  // which are only known after downloading them; so `open` waits for all of them, and the size is limited by `MAX_ARCHIVE`
+  // the attachments are not kept, and the content is dropped when the last process reading it closes it
+  Archive {
+    root: u64,
+    content: Bytes,
+    // processes having it open in the mount, which read the same content
+    readers: u32,
+  },
   // its children can be `Item` or `Discussion`
-  ItemList(Map),
+  // the `usize` is its index in `Names::course_content`
+  ItemList(Map, usize),
   // an `Item` can be a homework/notification/file
   // Map value points to `Content` or `SubmitHomework` or `Refresh`
-  Item(Vec<(Cow<'static, str>, u64)>),
-  Content(Content),
+  Item(Vec<(Cow<'static, str>, u64)>, Xattrs),
+  Content(Content<B>),
   Discussion {
     course_discussion: Arc<(Arc<String>, String)>,
     board: String,
-    client: Arc<LearnHelper>,
+    client: Arc<B>,
     // len() == 1 for un-fetched replies, [0] is `Refresh`
     replies: Map,
   },
   DiscussionReply {
     course_discussion: Arc<(Arc<String>, String)>,
     id: Arc<Option<String>>,
-    client: Arc<LearnHelper>,
+    client: Arc<B>,
     content: String,
   },
   SubmitHomework {
     student_homework: Arc<String>,
-    client: Arc<LearnHelper>,
+    client: Arc<B>,
   },
   Refresh {
     parent: u64,
-    client: Arc<LearnHelper>,
+    client: Arc<B>,
     info: RefreshInfo,
   },
+  // an ino freed by `rmdir <student id>`, it can't be reused because the kernel may still refer to it
+  Removed,
+  // `已读` of a notification/file, writing `是` to it marks the item as read on the server
+  ReadState {
+    read: bool,
+    // visiting this url marks the item as read
+    url: String,
+    client: Arc<B>,
+    // the `Item` containing it, whose `read`/`new` xattr should be kept in sync
+    item: u64,
+  },
 }
 
-enum Content {
+enum Content<B> {
   Data(Bytes),
-  Url(String, Arc<LearnHelper>),
+  Url(String, Arc<B>),
+  // `Url` becomes `Downloaded` after `open`, the url is kept for `user.learn.url`
+  Downloaded(String, Bytes),
 }
 
-impl Content {
+impl<B> Content<B> {
   // if my implementation is correct, user can never read the content of the file when it is a `Url` variant
   // so its data is not important, just return empty slice here
   fn bytes(&self) -> &[u8] {
-    match self { Content::Data(x) => x, Content::Url(_, _) => &[] }
+    match self { Content::Data(x) | Content::Downloaded(_, x) => x, Content::Url(_, _) => &[] }
   }
 }
 
+// extended attributes of a node, the names here don't contain the `XATTR_PREFIX`
+type Xattrs = Vec<(&'static str, String)>;
+
+const XATTR_PREFIX: &str = "user.learn.";
+
 enum RefreshInfo {
   Homework { course: String, homework: String },
   Discussion, // currently no extra data needs to be kept
 }
 
 // `FileSystem` ino id starts from 1, fill `inos[0]` with `Root`, though it won't be accessed
-struct LearnFS {
-  inos: Vec<InoInfo>,
-  runtime: Runtime,
+// `B` is where everything comes from, see `backend`
+struct LearnFS<B = LearnHelper> {
+  inos: Vec<InoInfo<B>>,
+  runtime: Executor,
+  // files outside any `User` are owned by the user who mounts the filesystem
+  uid: u32,
+  gid: u32,
+  // inos are only appended, so the `User` each ino belongs to is recorded as runs of (first ino, `User` ino)
+  // `None` means it belongs to no `User`, and is accessible to everyone
+  owners: Vec<(u64, Option<u64>)>,
+  config: Config,
+  // fixed names in the locale chosen by `config`
+  names: &'static Names,
 }
 
-impl LearnFS {
-  fn new() -> LearnFS {
+impl<B: Backend> LearnFS<B> {
+  fn new(config: Config) -> LearnFS<B> {
     LearnFS {
       inos: vec![Root { users: Vec::new() }, Root { users: Vec::new() }],
-      runtime: Runtime::new().unwrap(),
+      runtime: Executor::new(config.timeout),
+      uid: unsafe { libc::getuid() },
+      gid: unsafe { libc::getgid() },
+      owners: vec![(0, None)],
+      names: config.locale.names(),
+      config,
     }
   }
-}
 
-// all information are only valid when it is returned
-// in this way, when `open` modifies the size of a file, kernel will fetch the attr of this file immediately after `open`
-// so that subsequent `read` can provide correct amount of data
-const TTL: Duration = Duration::from_secs(0);
+  fn user_of(&self, ino: u64) -> Option<u64> {
+    let idx = match self.owners.binary_search_by_key(&ino, |x| x.0) { Ok(idx) => idx, Err(idx) => idx - 1 };
+    self.owners[idx].1
+  }
+
+  // the uid of the local user who owns `ino`
+  fn owner(&self, ino: u64) -> Option<u32> {
+    match self.user_of(ino).map(|user| &self.inos[user as usize]) { Some(User { uid, .. }) => Some(*uid), _ => None }
+  }
+
+  // inos pushed after calling it belong to `user`
+  fn set_owner(&mut self, user: Option<u64>) {
+    let start = self.inos.len() as u64;
+    match self.owners.last_mut() {
+      Some(last) if last.1 == user => {}
+      Some(last) if last.0 == start => last.1 = user,
+      _ => self.owners.push((start, user)),
+    }
+  }
+
+  // inos pushed after calling it belong to the same `User` as `ino`
+  // every method pushing inos into `ino` calls it first, so that no frontend needs to care about owners
+  fn inherit_owner(&mut self, ino: u64) { self.set_owner(self.user_of(ino)); }
+
+  // replace all inos belonging to `user` (including itself) with `Removed`, which drops all its `Arc<B>`
+  fn free_user(&mut self, user: u64) {
+    for (idx, &(start, owner)) in self.owners.iter().enumerate() {
+      if owner == Some(user) {
+        let end = self.owners.get(idx + 1).map(|x| x.0).unwrap_or(self.inos.len() as u64);
+        for ino in start..end { self.inos[ino as usize] = Removed; }
+      }
+    }
+  }
+
+  // root can access everything, just like in other filesystems
+  fn accessible(&self, uid: u32, ino: u64) -> bool {
+    match self.owner(ino) { Some(owner) => uid == owner || uid == 0, None => true }
+  }
+
+  // the permission bits describe what operations are meaningful on the file:
+  // `mkdir` in `Root` and `unlink` in `Discussion` are supported, so they are writable
+  // `SubmitHomework` and `Refresh` are write-only, `ReadState` and `DiscussionReply` are read-write, others are read-only
+  // `User` is only accessible to its owner
+  fn attr(&self, ino: u64) -> FileAttr {
+    let mut attr = match &self.inos[ino as usize] {
+      Root { .. } | Discussion { .. } => dir_attr(ino, 0o755),
+      User { .. } => dir_attr(ino, 0o700),
+      Semester { .. } | Course { .. } | ItemList(..) | Item(..) => dir_attr(ino, 0o555),
+      Content(c) => file_attr(ino, c.bytes().len() as u64, 0o444),
+      SemesterGrades { content, .. } | Archive { content, .. } => file_attr(ino, content.len() as u64, 0o444),
+      DiscussionReply { content, .. } => file_attr(ino, content.len() as u64, 0o644),
+      SubmitHomework { .. } | Refresh { .. } => file_attr(ino, 0, 0o222),
+      ReadState { read, .. } => file_attr(ino, self.names.bool2str(*read).len() as u64, 0o644),
+      // `getattr` reports `ENOENT` for it, and it never appears in any Map
+      Removed => file_attr(ino, 0, 0),
+    };
+    attr.uid = self.owner(ino).unwrap_or(self.uid);
+    attr.gid = self.gid;
+    attr
+  }
+
+  fn xattrs(&self, ino: u64) -> Xattrs {
+    match &self.inos[ino as usize] {
+      Item(_, x) => x.clone(),
+      Content(Content::Url(url, _)) | Content(Content::Downloaded(url, _)) => vec![("url", url.clone())],
+      DiscussionReply { course_discussion, id, .. } => {
+        let mut x = vec![("course_id", course_discussion.0.to_string()), ("discussion_id", course_discussion.1.clone())];
+        // a reply which is not sent by any student or teacher (i.e., the discussion itself) has no id
+        if let Some(id) = id.as_ref() { x.push(("id", id.clone())); }
+        x
+      }
+      _ => Vec::new(),
+    }
+  }
+
+  // entries of an `ItemList` shown by `readdir`, sorted and filtered according to `config.listing`
+  // `lookup` still uses the original Map, so hidden entries are still accessible by name
+  // the keys are read from xattrs of `Item`s, which are kept up to date by refreshing and marking read
+  fn list_view<'a>(&'a self, m: &'a Map, idx: usize) -> Vec<(&'a str, u64)> {
+    let listing = &self.config.listing;
+    let opt = match idx { 0 => &listing.homework, 1 => &listing.notification, 2 => &listing.file, _ => &listing.discussion };
+    let xattr = |ino: u64, k: &str| match &self.inos[ino as usize] {
+      Item(_, x) => x.iter().find(|x| x.0 == k).map(|x| x.1.clone()),
+      _ => None,
+    };
+    // it means "not submitted" for homework
+    let unread = |ino: u64| match idx {
+      0 => xattr(ino, "submitted").as_deref() == Some("false"),
+      1 => xattr(ino, "read").as_deref() == Some("false"),
+      _ => xattr(ino, "new").as_deref() == Some("true"),
+    };
+    // `meta.json` and other files are always listed first, the others are `Item`s or `Discussion`s
+    let (mut fixed, mut items): (Vec<_>, Vec<_>) = m.iter().map(|(k, ino)| (k.as_str(), *ino))
+      .partition(|&(_, ino)| matches!(self.inos[ino as usize], Content(_) | Archive { .. }));
+    if (idx == 0 && opt.hide_submitted) || ((idx == 1 || idx == 2) && opt.hide_read) { items.retain(|&(_, ino)| unread(ino)); }
+    match opt.sort {
+      SortBy::None => {}
+      SortBy::Deadline => items.sort_by_cached_key(|&(_, ino)| xattr(ino, "deadline")),
+      SortBy::PublishTime => items.sort_by_cached_key(|&(_, ino)| xattr(ino, "publish_time")),
+      SortBy::Title => items.sort_by_key(|&(name, _)| name),
+      SortBy::Unread => items.sort_by_key(|&(_, ino)| !unread(ino)),
+    }
+    if opt.reverse { items.reverse(); }
+    fixed.append(&mut items);
+    fixed
+  }
+
+  // `ino` must be a `ReadState`; the server provides no way to mark an item as unread, so it fails with `EPERM`
+  fn set_read(&mut self, ino: u64, read: bool) -> Result<(), c_int> {
+    let item = match &mut self.inos[ino as usize] {
+      ReadState { read: old, url, client, item } => {
+        if *old == read { return Ok(()); }
+        if !read { return Err(EPERM); }
+        match self.runtime.block_on(client.download(url, self.runtime.timeout())) {
+          Ok(page) if backend::is_login_page(&page) => return (warn!("line {}: got the login page", line!()), Err(EACCES)).1,
+          Ok(_) => {}
+          Err(e) => return (warn!("line {}: {:?}", line!(), e), Err(errno(&e))).1,
+        }
+        *old = true;
+        *item
+      }
+      _ => return Err(EPERM),
+    };
+    if let Item(_, x) = &mut self.inos[item as usize] {
+      for (k, v) in x {
+        match *k { "read" => *v = true.to_string(), "new" => *v = false.to_string(), _ => {} }
+      }
+    }
+    Ok(())
+  }
+
+  // set the `read` xattr of the `Item` at `ino`, which only notifications have
+  // a file has `new` instead, which is not settable, because `new` = false doesn't mean the same as `read` = true
+  // `ENOTSUP` for anything else, though its `已读` can still be written
+  fn set_item_read(&mut self, ino: u64, read: bool) -> Result<(), c_int> {
+    let state = match &self.inos[ino as usize] {
+      Item(m, x) if x.iter().any(|(k, _)| *k == "read") => do_lookup(m, self.names.read),
+      _ => None,
+    };
+    self.set_read(state.ok_or(ENOTSUP)?, read)
+  }
+}
 
 // all the `time` fields are `UNIX_EPOCH`, which is 1970-1-1, a useless value
-// `uid` & `gid` being 1000 means the normal user in most linux systems
-fn dir_attr(ino: u64) -> FileAttr {
-  FileAttr { ino, size: 0, blocks: 0, atime: UNIX_EPOCH, mtime: UNIX_EPOCH, ctime: UNIX_EPOCH, crtime: UNIX_EPOCH, kind: Directory, perm: 0o777, nlink: 2, uid: 1000, gid: 1000, rdev: 0, flags: 0 }
+// `uid` & `gid` are filled by `LearnFS::attr`
+fn dir_attr(ino: u64, perm: u16) -> FileAttr {
+  FileAttr { ino, size: 0, blocks: 0, atime: UNIX_EPOCH, mtime: UNIX_EPOCH, ctime: UNIX_EPOCH, crtime: UNIX_EPOCH, kind: Directory, perm, nlink: 2, uid: 0, gid: 0, rdev: 0, flags: 0 }
 }
 
-fn file_attr(ino: u64, size: u64) -> FileAttr {
-  FileAttr { ino, size, blocks: 1, atime: UNIX_EPOCH, mtime: UNIX_EPOCH, ctime: UNIX_EPOCH, crtime: UNIX_EPOCH, kind: RegularFile, perm: 0o666, nlink: 1, uid: 1000, gid: 1000, rdev: 0, flags: 0 }
+fn file_attr(ino: u64, size: u64, perm: u16) -> FileAttr {
+  FileAttr { ino, size, blocks: 1, atime: UNIX_EPOCH, mtime: UNIX_EPOCH, ctime: UNIX_EPOCH, crtime: UNIX_EPOCH, kind: RegularFile, perm, nlink: 1, uid: 0, gid: 0, rdev: 0, flags: 0 }
 }
 
 // print prompt message to stdout and read password from stdin of the given process
-fn get_password(pid: u32) -> std::io::Result<String> {
+fn get_password(pid: u32, prompt: &str) -> std::io::Result<String> {
   use std::fs::{File, OpenOptions};
   use std::io::{Write, BufReader, BufRead};
   // magic, use procfs to get the file handle of stdin/stdout of another process
   // in `read_file` we can also get teh cwd of another process
   let mut stdout = OpenOptions::new().write(true).open(format!("/proc/{}/fd/1", pid))?;
-  stdout.write_all("请输入密码：".as_bytes())?;
+  stdout.write_all(prompt.as_bytes())?;
   stdout.flush()?;
   let mut stdin = BufReader::new(File::open(format!("/proc/{}/fd/0", pid))?);
   let mut password = String::new();
@@ -155,434 +400,722 @@ macro_rules! try_push {
   };
 }
 
-fn bool2str(b: bool) -> &'static str { if b { "是" } else { "否" } }
+// all times in `meta.json` are in ISO 8601 format, without time zone (the server uses Beijing time)
+fn iso(t: &NaiveDateTime) -> String { t.format("%Y-%m-%dT%H:%M:%S").to_string() }
+
+fn pretty(v: &Value) -> String { serde_json::to_string_pretty(v).unwrap() }
+
+fn attachment(name: Option<&String>, url: Option<&String>) -> Value {
+  match (name, url) { (Some(name), Some(url)) => json!({ "name": name, "url": url }), _ => Value::Null }
+}
+
+// the `xxx_meta` functions collect all fields of an item in machine-readable form
+// they are served as `meta.json` in each `Item`, and as an array in `meta.json` of each `ItemList`
+fn homework_meta(h: &Homework) -> Value {
+  let d = &h.detail;
+  let name_url = |x: &Option<(String, String)>| attachment(x.as_ref().map(|x| &x.0), x.as_ref().map(|x| &x.1));
+  json!({
+    "id": h.id, "course_id": h.course_id, "student_homework_id": h.student_homework_id, "title": h.title,
+    "description": d.description, "assign_time": iso(&h.assign_time), "deadline": iso(&h.deadline),
+    "submitted": h.submit_time.is_some(), "submit_time": h.submit_time.as_ref().map(iso), "submit_content": h.submit_content,
+    "graded": h.grade.is_some(), "grade": h.grade, "grade_time": h.grade_time.as_ref().map(iso),
+    "grader_name": h.grader_name, "grade_content": h.grade_content,
+    "attachment": name_url(&d.attachment_name_url), "submit_attachment": name_url(&d.submit_attachment_name_url),
+    "grade_attachment": name_url(&d.grade_attachment_name_url),
+  })
+}
+
+fn notification_meta(n: &Notification) -> Value {
+  json!({
+    "id": n.id, "title": n.title, "content": n.content, "publish_time": iso(&n.publish_time), "publisher": n.publisher,
+    "read": n.read, "important": n.important, "attachment": attachment(n.attachment_name.as_ref(), n.attachment_url.as_ref()),
+  })
+}
+
+fn file_meta(f: &File) -> Value {
+  json!({
+    "id": f.id, "title": f.title, "description": f.description, "size": f.size, "upload_time": iso(&f.upload_time),
+    "read": !f.new, "important": f.important, "visit_count": f.visit_count, "download_count": f.download_cunt,
+    "file_type": f.file_type, "attachment": attachment(Some(&(f.title.clone() + "." + &f.file_type)), Some(&f.download_url())),
+  })
+}
+
+// the `xxx_name` functions provide variables for the naming templates in `config::Naming`
+fn homework_name(h: &Homework, template: &str) -> String {
+  render(template, |k| Some(match k {
+    "title" => Var::Str(h.title.clone()), "assign_time" => Var::Time(h.assign_time), "deadline" => Var::Time(h.deadline), _ => return None,
+  }))
+}
+
+fn notification_name(n: &Notification, template: &str) -> String {
+  render(template, |k| Some(match k {
+    "title" => Var::Str(n.title.clone()), "publish_time" => Var::Time(n.publish_time), "publisher" => Var::Str(n.publisher.clone()), _ => return None,
+  }))
+}
+
+fn file_name(f: &File, template: &str) -> String {
+  render(template, |k| Some(match k { "title" => Var::Str(f.title.clone()), "upload_time" => Var::Time(f.upload_time), _ => return None }))
+}
+
+fn discussion_meta(d: &thu_learn_helper::types::Discussion) -> Value {
+  json!({ "id": d.id, "title": d.title, "board_id": d.board_id })
+}
+
+// the `xxx_xattrs` functions provide a subset of `xxx_meta`, which are the fields most useful for querying
+fn homework_xattrs(h: &Homework) -> Xattrs {
+  let mut x = vec![("id", h.id.clone()), ("course_id", h.course_id.clone()), ("student_homework_id", h.student_homework_id.clone()),
+    ("publish_time", iso(&h.assign_time)), ("deadline", iso(&h.deadline)), ("submitted", h.submit_time.is_some().to_string())];
+  if let Some(grade) = &h.grade { x.push(("grade", grade.to_string())); }
+  x
+}
+
+fn notification_xattrs(n: &Notification, course: &str) -> Xattrs {
+  vec![("id", n.id.clone()), ("course_id", course.to_owned()), ("publish_time", iso(&n.publish_time)), ("important", n.important.to_string()),
+    ("read", n.read.to_string())]
+}
+
+fn file_xattrs(f: &File, course: &str) -> Xattrs {
+  vec![("id", f.id.clone()), ("course_id", course.to_owned()), ("publish_time", iso(&f.upload_time)), ("important", f.important.to_string()),
+    ("new", f.new.to_string()), ("size", f.size.to_string())]
+}
+
+const LEARN_PREFIX: &str = "https://learn.tsinghua.edu.cn";
+
+// the `xxx_read_state` functions return whether the item is read, and the url visiting which marks it as read in the web UI
+// a notification is marked when its detail page is visited, and a file when it is downloaded
+fn notification_read_state(n: &Notification, course: &str) -> (bool, String) {
+  (n.read, format!("{}/f/wlxt/kcgg/wlkc_ggb/student/beforeViewXs?wlkcid={}&id={}", LEARN_PREFIX, course, n.id))
+}
+
+// the url of the attachment doesn't need `_course`
+fn file_read_state(f: &File, _course: &str) -> (bool, String) {
+  (!f.new, f.download_url())
+}
+
+// `$name` is the file of an html `$body`, which is converted to `$format`
+// if it is not kept in html format, the original html is put in a sibling file with suffix `.html`
+macro_rules! push_body {
+  ($map: expr, $content: expr, $ino: expr, $format: expr, $name: expr => $body: expr) => {
+    push!($map, $content, $ino, $name => html::convert(&$body, $format));
+    if $format != BodyFormat::Html { push!($map, $content, $ino, format!("{}.html", $name) => $body); }
+  };
+}
 
 // if `content_only == false`, will push extra 2 items to `m`, respectively `SubmitHomework` and `Refresh`
-fn homework_content(h: Homework, mut i: u64, client: &Arc<LearnHelper>, content_only: bool) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content>) {
+fn homework_content<B>(h: Homework, mut i: u64, client: &Arc<B>, names: &Names, body: BodyFormat, content_only: bool) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content<B>>) {
+  let meta = pretty(&homework_meta(&h));
   let HomeworkDetail { description, attachment_name_url, submit_attachment_name_url, grade_attachment_name_url } = h.detail;
   let (mut m, mut c) = (Vec::new(), Vec::new());
   if !content_only {
     // they should added in the caller side of `homework_content`
-    m.push(("提交作业".into(), inc!(i))); // it maps to SubmitHomework
-    m.push(("刷新".into(), inc!(i))); // it maps to Refresh
+    m.push((names.submit.into(), inc!(i))); // it maps to SubmitHomework
+    m.push((names.refresh.into(), inc!(i))); // it maps to Refresh
   }
-  push!(m, c, i, "描述" => description, "发布时间" => h.assign_time.to_string(), "截止时间" => h.deadline.to_string());
-  try_push!(m, c, i, "提交时间" => h.submit_time.map(|x| x.to_string()), "提交内容" => h.submit_content,
-    "成绩" => h.grade.map(|x| x.to_string()), "批阅时间" => h.grade_time.map(|x| x.to_string()),
-    "批阅老师" => h.grader_name, "评语" => h.grade_content);
+  push_body!(m, c, i, body, names.description => description);
+  push!(m, c, i, names.assign_time => h.assign_time.to_string(), names.deadline => h.deadline.to_string(), "meta.json" => meta);
+  try_push!(m, c, i, names.submit_time => h.submit_time.map(|x| x.to_string()), names.submit_content => h.submit_content,
+    names.grade => h.grade.map(|x| x.to_string()), names.grade_time => h.grade_time.map(|x| x.to_string()),
+    names.grader => h.grader_name, names.grade_content => h.grade_content);
   if let Some((name, url)) = attachment_name_url {
-    m.push((format!("附件：{}", name).into(), inc!(i)));
+    push_unique(&mut m, &format!("{}{}", names.attachment, name), inc!(i));
     c.push(Content::Url(url, Arc::clone(client)));
   }
   if let Some((name, url)) = submit_attachment_name_url {
-    m.push((format!("提交附件：{}", name).into(), inc!(i)));
+    push_unique(&mut m, &format!("{}{}", names.submit_attachment, name), inc!(i));
     c.push(Content::Url(url, Arc::clone(client)));
   }
   if let Some((name, url)) = grade_attachment_name_url {
-    m.push((format!("评语附件：{}", name).into(), i));
+    push_unique(&mut m, &format!("{}{}", names.grade_attachment, name), i);
     c.push(Content::Url(url, Arc::clone(client)));
   }
   (m, c)
 }
 
-fn notification_content(n: Notification, mut i: u64, client: Arc<LearnHelper>) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content>) {
+// `notification_content` and `file_content` will push `已读` to `m` first, which maps to `ReadState` added in the caller side
+fn notification_content<B>(n: Notification, mut i: u64, client: Arc<B>, names: &Names, body: BodyFormat) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content<B>>) {
   let (mut m, mut c) = (Vec::new(), Vec::new());
-  push!(m, c, i, "内容" => n.content, "发布时间" => n.publish_time.to_string(), "发布老师" => n.publisher, "已读" => bool2str(n.read), "重要" => bool2str(n.important));
+  let meta = pretty(&notification_meta(&n));
+  m.push((names.read.into(), inc!(i)));
+  push_body!(m, c, i, body, names.content => n.content);
+  push!(m, c, i, names.publish_time => n.publish_time.to_string(), names.publisher => n.publisher,
+    names.important => names.bool2str(n.important), "meta.json" => meta);
   if let (Some(name), Some(url)) = (n.attachment_name, n.attachment_url) {
-    m.push((format!("通知附件：{}", name).into(), i));
+    push_unique(&mut m, &format!("{}{}", names.notification_attachment, name), i);
     c.push(Content::Url(url, client));
   }
   (m, c)
 }
 
-fn file_content(f: File, mut i: u64, client: Arc<LearnHelper>) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content>) {
+// the description of a file is plain text, so `_body` is not used
+fn file_content<B>(f: File, mut i: u64, client: Arc<B>, names: &Names, _body: BodyFormat) -> (Vec<(Cow<'static, str>, u64)>, Vec<Content<B>>) {
   let (mut m, mut c) = (Vec::new(), Vec::new());
   let url = f.download_url();
-  push!(m, c, i, "描述" => f.description, "大小" => f.size, "上传时间" => f.upload_time.to_string(), "已读" => bool2str(!f.new),
-    "重要" => bool2str(f.important), "访问次数" => f.visit_count.to_string(), "下载次数" => f.download_cunt.to_string());
-  m.push(((f.title + "." + &f.file_type).into(), i));
+  let meta = pretty(&file_meta(&f));
+  m.push((names.read.into(), inc!(i)));
+  push!(m, c, i, names.description => f.description, names.size => f.size, names.upload_time => f.upload_time.to_string(),
+    names.important => names.bool2str(f.important), names.visit_count => f.visit_count.to_string(),
+    names.download_count => f.download_cunt.to_string(), "meta.json" => meta);
+  push_unique(&mut m, &(f.title + "." + &f.file_type), i);
   c.push(Content::Url(url, client));
   (m, c)
 }
 
-macro_rules! unwrap {
-  ($res: expr, $reply: expr) => {
-    match $res { Ok(x) => x, Err(e) => return (warn!("line {}: {:?}", line!(), e), $reply.error(EIO)).1 }
+// like `unwrap!` in `fuse_fs`, for functions returning `Result<_, c_int>`
+macro_rules! unwrap_or_errno {
+  ($res: expr) => {
+    match $res { Ok(x) => x, Err(e) => return (warn!("line {}: {:?}", line!(), e), Err(errno(&e))).1 }
   };
 }
 
-const COURSE_CONTENT: [&str; 4] = ["作业", "通知", "文件", "讨论"];
+// homework, notification, file, discussion; their names are in `Names::course_content`
+const COURSE_CONTENT_LEN: usize = 4;
+
+// the last character of a semester id is 1/2/3 for fall/spring/summer, unknown ones are kept as is
+fn semester_name(id: &str, names: &Names) -> String {
+  match id.chars().last() {
+    Some(c @ '1'..='3') => id[..id.len() - 1].to_owned() + names.semester[c as usize - '1' as usize],
+    _ => id.to_owned(),
+  }
+}
+
+// children of a `Course`: its `ItemList`s, followed by the course information files and the grade summary
+fn course_children(names: &'static Names, ino: u64) -> impl Iterator<Item=(&'static str, u64)> {
+  names.course_content.iter().copied().chain([names.course_info, names.course_info_json, names.grade_summary, names.archive].iter().copied()).zip(ino + 1..)
+}
+
+// attachments downloaded at the same time for an `Archive`
+const ARCHIVE_DOWNLOADS: usize = 4;
+
+// the largest `Archive`, which is built in memory; a larger one fails with `EFBIG`
+// it is also far from the 4 GiB limit of zip without zip64, which the `zip` crate doesn't write
+const MAX_ARCHIVE: usize = 1 << 30;
+
+fn csv_field(s: &str) -> Cow<str> {
+  if s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') { format!("\"{}\"", s.replace('"', "\"\"")).into() } else { s.into() }
+}
+
+// `成绩汇总.csv` of a course, or of a semester with an extra column of course names
+fn grade_csv(courses: &[(&str, &[Homework])], with_course: bool, names: &Names) -> String {
+  let mut header = vec![names.title, names.deadline, names.submit_time, names.grade, names.grader, names.grade_content];
+  if with_course { header.insert(0, names.course_name); }
+  let mut ret = header.join(",") + "\n";
+  for (course, hs) in courses {
+    for h in *hs {
+      let mut row = vec![h.title.clone(), iso(&h.deadline), h.submit_time.as_ref().map(iso).unwrap_or_default(),
+        h.grade.as_ref().map(|x| x.to_string()).unwrap_or_default(), h.grader_name.clone().unwrap_or_default(),
+        h.grade_content.clone().unwrap_or_default()];
+      if with_course { row.insert(0, course.to_string()); }
+      let row = row.iter().map(|x| csv_field(x)).collect::<Vec<_>>();
+      ret += &row.join(",");
+      ret.push('\n');
+    }
+  }
+  ret
+}
+
+// the text and json content of the course information files
+fn course_info(c: &thu_learn_helper::types::Course, names: &Names) -> (String, String) {
+  let lines = [
+    (names.course_name, c.name.clone()), (names.english_name, c.english_name.clone()),
+    (names.course_number, c.course_number.clone()), (names.course_index, c.course_index.to_string()),
+    (names.teacher, c.teacher_name.clone()), (names.teacher_number, c.teacher_number.clone()),
+    (names.time_location, c.time_location.join("; ")),
+  ];
+  let text = lines.iter().map(|(k, v)| format!("{}: {}\n", k, v)).collect();
+  let json = json!({
+    "id": c.id, "name": c.name, "english_name": c.english_name, "course_number": c.course_number, "course_index": c.course_index,
+    "teacher_name": c.teacher_name, "teacher_number": c.teacher_number, "time_location": c.time_location,
+  });
+  (text, pretty(&json))
+}
+
+impl<B: Backend> LearnFS<B> {
+  // log in a student and add it to the root with its semesters; return the ino of the new `User`
+  fn login(&mut self, name: &str, password: &str, uid: u32) -> Result<u64, BoxError> {
+    let cl = Arc::new(self.runtime.request(B::login(name, password))?);
+    let ss = self.runtime.request(cl.semester_id_list())?;
+    let (new_ino, names) = (self.inos.len() as u64, self.names);
+    // the new `User` and everything in it belong to itself
+    self.set_owner(Some(new_ino));
+    // each semester is followed by its `SemesterGrades`
+    let mut ss1 = Vec::new();
+    for (s, ino) in ss.iter().zip((new_ino + 1..).step_by(2)) { push_unique(&mut ss1, &semester_name(s, names), ino); }
+    // ino 1 is the root of the filesystem
+    if let Root { users } = &mut self.inos[1] { users.push((name.to_owned(), new_ino)); }
+    self.inos.push(User { semesters: ss1, uid, client: Arc::clone(&cl) });
+    for s in ss {
+      let semester = self.inos.len() as u64;
+      // it is pushed before any course, so that a course with the same name gets a suffix instead of it
+      let courses = vec![(names.grade_summary.to_owned(), semester + 1)];
+      self.inos.push(Semester { id: s, client: Arc::clone(&cl), courses, fetched: false });
+      self.inos.push(SemesterGrades { semester, content: Bytes::new(), readers: 0 });
+    }
+    Ok(new_ino)
+  }
 
-impl LearnFS {
-  // fetch discussion replies when it doesn't exist(equivalent to `replies.len() == 1`); return true for success
-  fn fetch_discussion_replies(&mut self, ino: u64) -> bool {
+  // fetch courses of a semester when they are not fetched
+  fn fetch_courses(&mut self, ino: u64) -> Result<(), c_int> {
+    self.inherit_owner(ino);
     let mut new_ino = self.inos.len() as u64;
     match &mut self.inos[ino as usize] {
-      Discussion { course_discussion, board, client, replies } => {
-        if replies.len() == 1 {
-          let (course, discussion) = (&course_discussion.0, &course_discussion.1);
-          let replies1 = match self.runtime.block_on(client.discussion_replies(course, discussion, board)) {
-            Ok(x) => x,
-            Err(e) => return (warn!("line {}: {:?}", line!(), e), false).1,
-          };
-          for (i, r) in replies1.iter().enumerate() {
-            replies.push((format!("{}楼-{}-{}", i, r.author, r.publish_time), inc!(new_ino)));
-            for (j, r) in r.replies.iter().enumerate() {
-              replies.push((format!("{}楼-回复{}-{}-{}", i, j, r.author, r.publish_time), inc!(new_ino)));
-            }
+      Semester { id, client, courses, fetched } => {
+        if !*fetched {
+          let cs = unwrap_or_errno!(self.runtime.request(client.course_list(id)));
+          *fetched = true;
+          // each course is followed by its `ItemList`s and course information files, see `course_children`
+          for c in &cs {
+            push_unique(courses, &c.name, new_ino);
+            new_ino += COURSE_CONTENT_LEN as u64 + 5;
           }
-          let (course_discussion, client) = (Arc::clone(course_discussion), Arc::clone(client));
-          for r in replies1 {
-            // it can't be a function, because the types of the two `r`s are different
-            macro_rules! push {
-              ($r: expr) => {
-                self.inos.push(DiscussionReply {
-                  course_discussion: Arc::clone(&course_discussion),
-                  id: Arc::new($r.id),
-                  client: Arc::clone(&client),
-                  content: $r.content,
-                });
-              };
+          let client = Arc::clone(client);
+          for c in cs {
+            let (text, json) = course_info(&c, self.names);
+            let course = self.inos.len() as u64;
+            self.inos.push(Course { id: Arc::new(c.id), client: Arc::clone(&client), fetched: false });
+            for idx in 0..COURSE_CONTENT_LEN {
+              self.inos.push(ItemList(Vec::new(), idx));
             }
-            push!(r);
-            for r in r.replies { push!(r); }
+            self.inos.push(Content(Content::Data(text.into())));
+            self.inos.push(Content(Content::Data(json.into())));
+            // filled when the course is fetched
+            self.inos.push(Content(Content::Data(Bytes::new())));
+            self.inos.push(Archive { root: course, content: Bytes::new(), readers: 0 });
           }
         }
-        true
+        Ok(())
       }
-      _ => unreachable!(),
+      _ => Err(ENOTDIR),
     }
   }
-}
 
-impl Filesystem for LearnFS {
-  fn lookup(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEntry) {
-    info!("lookup parent={} name={:?}", parent, name);
-    let ref name = name.to_string_lossy();
-    fn reply_map<S: Borrow<str>>(m: impl IntoIterator<Item=impl Borrow<(S, u64)>>, name: &str, reply: ReplyEntry) {
-      if let Some(ino) = do_lookup(m, &name) {
-        reply.entry(&TTL, &dir_attr(ino), 0);
-      } else { reply.error(ENOENT); }
+  // fill the content of a file which is downloaded or generated on `open`
+  fn load(&mut self, ino: u64) -> Result<(), c_int> {
+    if let Content(Content::Url(url, client)) = &mut self.inos[ino as usize] {
+      self.inos[ino as usize] = Content(Content::Downloaded(url.clone(), unwrap_or_errno!(self.runtime.block_on(client.download(url, self.runtime.timeout()))));
     }
-    match &mut self.inos[parent as usize] {
-      Root { users: m } | User { semesters: m, .. } | Semester { courses: m } | ItemList(m) =>
-        reply_map(m, name, reply),
-      Item(m) =>
-        if let Some(ino) = do_lookup(m, &name) {
-          match &self.inos[ino as usize] {
-            Content(c) => reply.entry(&TTL, &file_attr(ino, c.bytes().len() as u64), 0),
-            SubmitHomework { .. } | Refresh { .. } => reply.entry(&TTL, &file_attr(ino, 0), 0),
-            _ => unreachable!(),
-          }
-        } else { reply.error(ENOENT); }
-      // we need to fetch replies in both `lookup` and `readdir`, the former will be first called when
-      // user opens a file; the latter will be first called when user types `ls`
-      Discussion { .. } => {
-        self.fetch_discussion_replies(parent);
-        match &mut self.inos[parent as usize] {
-          Discussion { replies: m, .. } =>
-            if let Some(ino) = do_lookup(m, &name) {
-              match &self.inos[ino as usize] {
-                DiscussionReply { content, .. } => reply.entry(&TTL, &file_attr(ino, content.bytes().len() as u64), 0),
-                Refresh { .. } => reply.entry(&TTL, &file_attr(ino, 0), 0),
-                _ => unreachable!(),
-              }
-            } else { reply.error(ENOENT); }
-          _ => unreachable!(),
+    // an `Archive` which is being read is not generated again, so that all readers see the same content
+    if let Archive { root, readers: 0, .. } = self.inos[ino as usize] {
+      use std::io::{Cursor, Write};
+      let mut files = Vec::new();
+      self.attachments(root, String::new(), &mut files);
+      // attachments already downloaded by `open` are reused, the others are downloaded a few at a time and dropped once written,
+      // so that no more than `ARCHIVE_DOWNLOADS` of them are in memory besides the archive
+      let files = files.into_iter().filter_map(|(path, x)| match &self.inos[x as usize] {
+        Content(Content::Url(url, client)) => Some((path, Err((url.clone(), Arc::clone(client))))),
+        Content(Content::Downloaded(_, b)) => Some((path, Ok(b.clone()))),
+        _ => None,
+      }).collect::<Vec<_>>();
+      let timeout = self.runtime.timeout();
+      let mut downloads = stream::iter(files).map(move |(path, x)| async move {
+        match x {
+          Ok(b) => Ok((path, b)),
+          Err((url, client)) => client.download(&url, timeout).await.map(|b| (path, b)),
         }
-      }
-      // going into any child dir representing course content must first call `lookup`, so fill the content of them here
-      Course { id, client, fetched } => {
-        if !*fetched {
-          let (course, client) = (Arc::clone(id), Arc::clone(client));
-          let (hs, ns, fs, ds) = unwrap!(self.runtime.block_on(try_join4(
-            client.homework_list(&course), client.notification_list(&course),
-            client.file_list(&course), client.discussion_list(&course))), reply);
-          *fetched = true;
-          macro_rules! handle_items {
-            ($items: expr, $content_fn: expr, $offset: expr) => {
-              for x in $items {
-                let new_ino = self.inos.len() as u64;
-                let name = x.title.clone();
-                let (m, cs) = $content_fn(x, new_ino + 1, Arc::clone(&client));
-                self.inos.push(Item(m));
-                for c in cs { self.inos.push(Content(c)); }
-                match &mut self.inos[parent as usize + $offset] { ItemList(m) => m.push((name, new_ino)), _ => unreachable!() }
-              }
-            };
-          }
-          for mut h in hs {
-            fn get(s: &mut String) -> String { std::mem::replace(s, String::new()) }
-            let new_ino = self.inos.len() as u64;
-            let (name, sh, course, homework) =
-              (get(&mut h.title), get(&mut h.student_homework_id), get(&mut h.course_id), get(&mut h.id));
-            let (m, cs) = homework_content(h, new_ino + 1, &client, false);
-            self.inos.push(Item(m));
-            self.inos.push(SubmitHomework { student_homework: Arc::new(sh), client: Arc::clone(&client) });
-            self.inos.push(Refresh { parent: new_ino, client: Arc::clone(&client), info: RefreshInfo::Homework { course, homework } });
-            for c in cs { self.inos.push(Content(c)); }
-            match &mut self.inos[parent as usize + 1] { ItemList(m) => m.push((name, new_ino)), _ => unreachable!() }
-          }
-          handle_items!(ns, notification_content, 2);
-          handle_items!(fs, file_content, 3);
-          for d in ds {
-            let new_ino = self.inos.len() as u64;
-            self.inos.push(Discussion {
-              course_discussion: Arc::new((Arc::clone(&course), d.id)),
-              board: d.board_id,
-              client: Arc::clone(&client),
-              replies: vec![("刷新".into(), new_ino + 1)],
-            });
-            self.inos.push(Refresh { parent: new_ino, client: Arc::clone(&client), info: RefreshInfo::Discussion });
-            match &mut self.inos[parent as usize + 4] { ItemList(m) => m.push((d.title, new_ino)), _ => unreachable!() }
-          }
+      }).buffered(ARCHIVE_DOWNLOADS);
+      // attachments are mostly compressed formats like pdf/pptx, so they are stored without compressing again
+      let mut w = zip::ZipWriter::new(Cursor::new(Vec::new()));
+      let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+      let mut total = 0;
+      unwrap_or_errno!(self.runtime.block_on(async {
+        while let Some((path, data)) = downloads.next().await.transpose()? {
+          total += data.len();
+          if total > MAX_ARCHIVE { return Err(backend::BackendError::TooLarge.into()); }
+          w.start_file(path, options)?;
+          w.write_all(&data)?;
         }
-        reply_map(COURSE_CONTENT.iter().copied().zip(parent + 1..), name, reply);
-      }
-      _ => reply.error(EPERM),
+        Ok::<_, BoxError>(())
+      }));
+      let zip = unwrap_or_errno!(w.finish().map_err(BoxError::from)).into_inner();
+      self.inos[ino as usize] = Archive { root, content: zip.into(), readers: 0 };
     }
+    // the same for a `SemesterGrades`, a reader in the middle of it would otherwise see parts of two versions
+    if let SemesterGrades { semester, readers: 0, .. } = self.inos[ino as usize] {
+      self.fetch_courses(semester)?;
+      let (client, courses) = match &self.inos[semester as usize] {
+        Semester { client, courses, .. } => (Arc::clone(client), courses.iter().filter_map(|(name, ino)| match &self.inos[*ino as usize] {
+          Course { id, .. } => Some((name.clone(), Arc::clone(id))),
+          _ => None,
+        }).collect::<Vec<_>>()),
+        _ => return Err(EIO),
+      };
+      let requests = courses.iter().map(|(_, id)| self.runtime.limit(client.homework_list(id))).collect::<Vec<_>>();
+      let hs = unwrap_or_errno!(self.runtime.block_on(try_join_all(requests)));
+      let courses = courses.iter().zip(&hs).map(|((name, _), hs)| (name.as_str(), &hs[..])).collect::<Vec<_>>();
+      self.inos[ino as usize] = SemesterGrades { semester, content: grade_csv(&courses, true, self.names).into(), readers: 0 };
+    }
+    Ok(())
   }
 
-  fn getattr(&mut self, _req: &Request, ino: u64, reply: ReplyAttr) {
-    info!("getattr ino={}", ino);
+  // fetch a directory if needed, and return all its entries (not filtered by `list_view`)
+  fn children(&mut self, ino: u64) -> Result<Vec<(String, u64)>, c_int> {
     match &self.inos[ino as usize] {
-      Root { .. } | User { .. } | Semester { .. } | Course { .. } | ItemList(_) | Item(_) | Discussion { .. } => reply.attr(&TTL, &dir_attr(ino)),
-      Content(c) => reply.attr(&TTL, &file_attr(ino, c.bytes().len() as u64)),
-      DiscussionReply { content, .. } => reply.attr(&TTL, &file_attr(ino, content.len() as u64)),
-      SubmitHomework { .. } | Refresh { .. } => reply.attr(&TTL, &file_attr(ino, 0)),
-    }
-  }
-
-  fn setattr(&mut self, req: &Request, ino: u64, _mode: Option<u32>, _uid: Option<u32>, _gid: Option<u32>, _size: Option<u64>, _atime: Option<SystemTime>, _mtime: Option<SystemTime>, _fh: Option<u64>, _crtime: Option<SystemTime>, _chgtime: Option<SystemTime>, _bkuptime: Option<SystemTime>, _flags: Option<u32>, reply: ReplyAttr) {
-    info!("setattr(forward to getattr) ino={}", ino);
-    self.getattr(req, ino, reply);
-  }
-
-  fn mkdir(&mut self, req: &Request, parent: u64, name: &OsStr, _mode: u32, reply: ReplyEntry) {
-    info!("mkdir parent={} name={:?}", parent, name);
-    let name = name.to_string_lossy();
-    let new_ino = self.inos.len() as u64;
-    match &mut self.inos[parent as usize] {
-      Root { users } => {
-        // we don't need to worry about duplication here, because a prior `lookup` call will prevent it
-        let password = unwrap!(get_password(req.pid()), reply);
-        let tmp = self.runtime.block_on(async {
-          let cl = LearnHelper::login(&name, &password).await?;
-          let ss = cl.semester_id_list().await?;
-          let cl1 = &cl;
-          let css = try_join_all(ss.iter().map(async move |s| cl1.course_list(s).await)).await?;
-          Ok::<_, Error>((Arc::new(cl), ss, css))
-        });
-        let (cl, ss, css) = unwrap!(tmp, reply);
-        let ss1 = ss.iter().map(|s| {
-          let (l, r) = s.split_at(s.len() - 1);
-          l.to_owned() + match r { "1" => "秋", "2" => "春", "3" => "夏", _ => panic!("invalid semester type"), }
-        }).zip(css.iter().scan(new_ino + 1, |sum, cs| (Some(*sum), *sum += (cs.len() * (COURSE_CONTENT.len() + 1)) as u64 + 1).0))
-          .collect();
-        users.push((name.into_owned(), new_ino));
-        self.inos.push(User { semesters: ss1 });
-        for mut cs in css {
-          let new_ino = self.inos.len() as u64;
-          let cs1 = cs.iter_mut().map(|c| std::mem::replace(&mut c.name, String::new()))
-            .zip((new_ino + 1..).step_by(COURSE_CONTENT.len() + 1)).collect();
-          self.inos.push(Semester { courses: cs1 });
-          for c in cs {
-            self.inos.push(Course { id: Arc::new(c.id), client: Arc::clone(&cl), fetched: false });
-            for _ in 0..COURSE_CONTENT.len() {
-              self.inos.push(ItemList(Vec::new()));
-            }
-          }
-        }
-        reply.entry(&TTL, &dir_attr(new_ino), 0);
-      }
-      _ => reply.error(EPERM),
-    }
-  }
-
-  // called when removing a file, now only useful for removing discussion replies
-  fn unlink(&mut self, _req: &Request, parent: u64, name: &OsStr, reply: ReplyEmpty) {
-    info!("unlink parent={} name={:?}", parent, name);
-    let name = name.to_string_lossy();
-    match &mut self.inos[parent as usize] {
-      Discussion { replies, .. } => {
-        if let Some(idx) = do_lookup(replies, &name) {
-          match &self.inos[idx as usize] {
-            DiscussionReply { course_discussion, id, client, .. } => {
-              let id = if let Some(x) = id.as_ref() { x } else { return reply.error(EPERM); };
-              let _ = unwrap!(self.runtime.block_on(client.delete_discussion_reply(&course_discussion.0, id)), reply);
-              reply.ok();
-            }
-            Refresh { .. } => reply.error(EPERM),
-            _ => unreachable!(),
-          }
-        } else { reply.error(ENOENT); }
-      }
-      _ => reply.error(EPERM),
+      Semester { .. } => self.fetch_courses(ino)?,
+      Course { .. } => self.fetch_course(ino)?,
+      Discussion { .. } => self.fetch_discussion_replies(ino)?,
+      _ => {}
     }
+    Ok(match &self.inos[ino as usize] {
+      Root { users: m } | User { semesters: m, .. } | Semester { courses: m, .. } | ItemList(m, _) | Discussion { replies: m, .. } => m.clone(),
+      Item(m, _) => m.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+      Course { .. } => course_children(self.names, ino).map(|(k, v)| (k.to_owned(), v)).collect(),
+      _ => return Err(ENOTDIR),
+    })
   }
 
-  // download the url when this file is Content::Url, nop (but not an error) for other files
-  fn open(&mut self, _req: &Request, ino: u64, _flags: u32, reply: ReplyOpen) {
-    info!("open ino={}", ino);
-    if let Content(Content::Url(url, client)) = &mut self.inos[ino as usize] {
-      self.inos[ino as usize] = Content(Content::Data(unwrap!(self.runtime.block_on(async {
-        client.0.get(url.as_str()).send().await?.bytes().await
-      }), reply)));
+  // `children` as listed to a user, sorted and filtered by `list_view`; hidden entries are still found by `resolve`
+  fn listing(&mut self, ino: u64) -> Result<Vec<(String, u64)>, c_int> {
+    let children = self.children(ino)?;
+    Ok(match &self.inos[ino as usize] {
+      ItemList(m, idx) => self.list_view(m, *idx).into_iter().map(|(k, v)| (k.to_owned(), v)).collect(),
+      _ => children,
+    })
+  }
+
+  // the ino of `path` relative to `ino`, whose components are separated by `/`
+  fn resolve(&mut self, mut ino: u64, path: &str) -> Result<u64, c_int> {
+    for name in path.split('/').filter(|x| !x.is_empty()) {
+      ino = do_lookup(&self.children(ino)?, name).ok_or(ENOENT)?;
     }
-    // this is the default implementation of `FileSystem`, returning such data makes the `open` operation useless
-    reply.opened(0, 0);
+    Ok(ino)
   }
 
-  fn read(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, size: u32, reply: ReplyData) {
-    info!("read ino={} offset={} size={}", ino, offset, size);
-    let reply_bytes = move |b: &[u8], reply: ReplyData| {
-      let offset = offset as usize;
-      reply.data(&b[offset..(offset + size as usize).min(b.len())])
+  // submit a homework whose `Item` (or the `SubmitHomework` in it) is `ino` and wait for the result
+  // `file` is the name and content of the attachment
+  fn submit(&mut self, ino: u64, content: String, file: Option<(&str, Vec<u8>)>) -> Result<(), c_int> {
+    let submit = match &self.inos[ino as usize] {
+      SubmitHomework { .. } => ino,
+      _ => do_lookup(&self.children(ino)?, self.names.submit).ok_or(ENOTDIR)?,
     };
-    match &self.inos[ino as usize] {
-      Content(c) => reply_bytes(c.bytes(), reply),
-      DiscussionReply { content, .. } => reply_bytes(content.as_bytes(), reply),
-      SubmitHomework { .. } | Refresh { .. } => reply.data(&[]),
-      _ => reply.error(EPERM),
+    let (student_homework, client) = match &self.inos[submit as usize] {
+      SubmitHomework { student_homework, client } => (Arc::clone(student_homework), Arc::clone(client)),
+      _ => return Err(ENOTDIR),
+    };
+    let size = content.len() + file.as_ref().map_or(0, |(_, x)| x.len());
+    match self.runtime.upload(size, client.submit_homework(&student_homework, content, file)) {
+      Ok(_) => Ok(()),
+      Err(e) => (warn!("failed to submit homework: {}", e), Err(errno(&e))).1,
     }
   }
 
-  fn write(&mut self, req: &Request, ino: u64, _fh: u64, _offset: i64, data: &[u8], _flags: u32, reply: ReplyWrite) {
-    info!("write ino={} data={:?}", ino, data);
-    fn parse_data(data: &[u8], pid: u32) -> Option<(String, Option<(&str, Vec<u8>)>)> {
-      let data = std::str::from_utf8(&data).ok()?;
-      let (file, content) = if data.starts_with("FILE=") {
-        let data = &data[5..];
-        let file_end = data.find(|x: char| x.is_whitespace()).unwrap_or(data.len());
-        (Some(&data[..file_end]), &data[file_end..])
-      } else { (None, data) };
-      let file = if let Some(f) = file { Some((f, read_file(pid, f).ok()?)) } else { None };
-      Some((content.to_owned(), file))
+  // reply to the `Discussion` at `ino` (or to its reply named `to`), or to the `DiscussionReply` at `ino`, and wait for the result
+  // `file` is the name and content of the attachment
+  fn reply_discussion(&mut self, ino: u64, to: Option<&str>, content: String, file: Option<(&str, Vec<u8>)>) -> Result<(), c_int> {
+    let (course_discussion, id, client) = if let DiscussionReply { course_discussion, id, client, .. } = &self.inos[ino as usize] {
+      (Arc::clone(course_discussion), Arc::clone(id), Arc::clone(client))
+    } else {
+      let children = self.children(ino)?;
+      let id = match to {
+        Some(to) => match &self.inos[do_lookup(&children, to).ok_or(ENOENT)? as usize] {
+          DiscussionReply { id, .. } => Arc::clone(id),
+          _ => return Err(EPERM),
+        },
+        None => Arc::new(None),
+      };
+      match &self.inos[ino as usize] {
+        Discussion { course_discussion, client, .. } => (Arc::clone(course_discussion), id, Arc::clone(client)),
+        _ => return Err(ENOTDIR),
+      }
+    };
+    let (course, discussion) = (&course_discussion.0, &course_discussion.1);
+    let size = content.len() + file.as_ref().map_or(0, |(_, x)| x.len());
+    match self.runtime.upload(size, client.reply_discussion(course, discussion, content, id.as_deref(), file)) {
+      Ok(_) => Ok(()),
+      Err(e) => (warn!("failed to reply discussion: {}", e), Err(errno(&e))).1,
     }
+  }
+
+  // refresh the `Item` or `Discussion` containing the `Refresh` at `ino`
+  fn refresh(&mut self, ino: u64) -> Result<(), c_int> {
+    self.inherit_owner(ino);
     match &self.inos[ino as usize] {
-      SubmitHomework { student_homework, client } => {
-        reply.written(data.len() as u32);
-        // the operation of fuse is not re-entrant, so we must finish `write` before we can start another operation
-        // I choose to spawn the handle finish this request, so that error handling must be ignored, because their is no way to fetch the result
-        let (pid, data) = (req.pid(), data.to_vec());
-        let (student_homework, client) = (Arc::clone(student_homework), Arc::clone(client));
-        self.runtime.spawn(async move {
-          let (content, file) = if let Some(x) = parse_data(&data, pid) { x } else { return; };
-          if let Err(e) = client.submit_homework(&student_homework, content, file).await {
-            warn!("failed to submit homework: {}", e);
-          } else { info!("submit homework done"); }
-        });
-      }
       Refresh { parent, client, info } => {
         let parent = *parent;
         match info {
           RefreshInfo::Homework { course, homework } => {
-            let hs = unwrap!(self.runtime.block_on(client.homework_list(course)), reply);
-            if let Some(h) = hs.into_iter().find(|h| &h.id == homework) {
-              let new_ino = self.inos.len() as u64;
-              let (mut m, cs) = homework_content(h, new_ino, client, true);
-              for c in cs { self.inos.push(Content(c)); }
-              let m1 = match &mut self.inos[parent as usize] { Item(m) => m, _ => unreachable!() };
-              // for convenience, except for the first two files, the space occupied by other files has not been recycled
-              // this does cause a waste of space, and it would be better if we had a global garbage collector
-              // now we can pretend that it exists, so there is no need to implement refreshing very carefully
-              m1.truncate(2);
-              m1.append(&mut m);
-            }
+            let hs = unwrap_or_errno!(self.runtime.request(client.homework_list(course)));
+            // `ENOENT` if the homework is deleted on the server
+            let h = hs.into_iter().find(|h| &h.id == homework).ok_or(ENOENT)?;
+            let new_ino = self.inos.len() as u64;
+            let xattrs = homework_xattrs(&h);
+            let (mut m, cs) = homework_content(h, new_ino, client, self.names, self.config.body, true);
+            for c in cs { self.inos.push(Content(c)); }
+            let (m1, x1) = match &mut self.inos[parent as usize] { Item(m, x) => (m, x), _ => return Err(EIO) };
+            *x1 = xattrs;
+            // for convenience, except for the first two files, the space occupied by other files has not been recycled
+            // this does cause a waste of space, and it would be better if we had a global garbage collector
+            // now we can pretend that it exists, so there is no need to implement refreshing very carefully
+            m1.truncate(2);
+            m1.append(&mut m);
           }
           RefreshInfo::Discussion => {
             match &mut self.inos[parent as usize] {
               // just truncate its length to 1, and subsequent operations will refill the data
               Discussion { replies, .. } => replies.truncate(1),
-              _ => unreachable!(),
+              _ => return Err(EIO),
             }
           }
         }
-        reply.written(data.len() as u32);
-      }
-      DiscussionReply { course_discussion, id, client, .. } => {
-        reply.written(data.len() as u32);
-        let (pid, data) = (req.pid(), data.to_vec());
-        let (course_discussion, id, client) = (Arc::clone(course_discussion), Arc::clone(id), Arc::clone(&client));
-        self.runtime.spawn(async move {
-          let (content, file) = if let Some(x) = parse_data(&data, pid) { x } else { return; };
-          let (course, discussion) = (&course_discussion.0, &course_discussion.1);
-          if let Err(e) = client.reply_discussion(course, discussion, content, id.as_deref(), file).await {
-            warn!("failed to reply discussion: {}", e);
-          } else { info!("reply discussion done"); }
-        });
       }
-      _ => reply.error(EPERM),
+      _ => return Err(EPERM),
     }
+    Ok(())
   }
 
-  // we never need flush or sync
-  fn flush(&mut self, _req: &Request, _ino: u64, _fh: u64, _lock_owner: u64, reply: ReplyEmpty) { reply.ok(); }
+  // drop the content of an `Archive` which nobody is reading, the next `load` generates it again
+  // frontends other than the mount call it as soon as they have sent the content
+  fn unload(&mut self, ino: u64) {
+    if let Archive { content, readers: 0, .. } = &mut self.inos[ino as usize] { *content = Bytes::new(); }
+  }
 
-  fn fsync(&mut self, _req: &Request, _ino: u64, _fh: u64, _datasync: bool, reply: ReplyEmpty) { reply.ok(); }
+  // the content of a readable file, `load` should be called first for some of them
+  fn data(&self, ino: u64) -> Option<&[u8]> {
+    match &self.inos[ino as usize] {
+      Content(c) => Some(c.bytes()),
+      SemesterGrades { content, .. } | Archive { content, .. } => Some(content),
+      DiscussionReply { content, .. } => Some(content.as_bytes()),
+      SubmitHomework { .. } | Refresh { .. } => Some(&[]),
+      ReadState { read, .. } => Some(self.names.bool2str(*read).as_bytes()),
+      _ => None,
+    }
+  }
 
-  // if all contents are represented as an array a, we must return a[offset..]
-  fn readdir(&mut self, _req: &Request, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
-    info!("readdir ino={} offset={}", ino, offset);
-    if offset < 1 { reply.add(ino, 1, Directory, "."); }
-    if offset < 2 { reply.add(ino, 2, Directory, ".."); }
-    fn reply_map<S: Borrow<str>>(m: impl IntoIterator<Item=impl Borrow<(S, u64)>>, offset: i64, mut reply: ReplyDirectory) {
-      for (idx, x) in m.into_iter().enumerate().skip((offset - 2).max(0) as usize) {
-        let (id, ino) = x.borrow();
-        reply.add(*ino, (idx + 3) as i64, Directory, id.borrow());
+  // collect the attachments in a `Course` or an `ItemList` for `Archive`, with paths relative to it
+  fn attachments(&self, ino: u64, prefix: String, files: &mut Vec<(String, u64)>) {
+    match &self.inos[ino as usize] {
+      Course { .. } => for (name, x) in self.names.course_content.iter().zip(ino + 1..) {
+        self.attachments(x, format!("{}{}/", prefix, name), files);
+      }
+      ItemList(m, _) => for (name, x) in m {
+        if let Item(..) = self.inos[*x as usize] { self.attachments(*x, format!("{}{}/", prefix, name), files); }
       }
-      reply.ok();
+      Item(m, _) => for (name, x) in m {
+        if let Content(Content::Url(..)) | Content(Content::Downloaded(..)) = self.inos[*x as usize] { files.push((format!("{}{}", prefix, name), *x)); }
+      }
+      _ => {}
     }
-    match &mut self.inos[ino as usize] {
-      Root { users: m } | User { semesters: m, .. } | Semester { courses: m } | ItemList(m) => reply_map(m, offset, reply),
-      Item(m) => reply_map(m, offset, reply),
-      Course { .. } => reply_map(COURSE_CONTENT.iter().copied().zip(ino + 1..), offset, reply),
-      Discussion { .. } => {
-        self.fetch_discussion_replies(ino);
-        match &mut self.inos[ino as usize] {
-          Discussion { replies, .. } => reply_map(replies, offset, reply),
-          _ => unreachable!(),
+  }
+
+  // fetch homeworks, notifications, files and discussions of a course when they are not fetched
+  fn fetch_course(&mut self, ino: u64) -> Result<(), c_int> {
+    let (course, client) = match &self.inos[ino as usize] {
+      Course { fetched: true, .. } => return Ok(()),
+      Course { id, client, .. } => (Arc::clone(id), Arc::clone(client)),
+      _ => return Err(ENOTDIR),
+    };
+    self.inherit_owner(ino);
+    let (hs, ns, fs, ds) = unwrap_or_errno!(self.runtime.block_on(try_join4(
+      self.runtime.limit(client.homework_list(&course)), self.runtime.limit(client.notification_list(&course)),
+      self.runtime.limit(client.file_list(&course)), self.runtime.limit(client.discussion_list(&course)))));
+    if let Course { fetched, .. } = &mut self.inos[ino as usize] { *fetched = true; }
+    // like `meta.json`, `Refresh` in an `Item` doesn't update it
+    self.inos[ino as usize + COURSE_CONTENT_LEN + 3] = Content(Content::Data(grade_csv(&[("", &hs[..])], false, self.names).into()));
+    // `meta.json` is pushed first, so that an item with the same title gets a suffix instead of it
+    let meta_ino = self.inos.len() as u64;
+    for offset in 0..COURSE_CONTENT_LEN {
+      self.inos.push(Content(Content::Data(Bytes::new())));
+      match &mut self.inos[ino as usize + 1 + offset] { ItemList(m, _) => m.push(("meta.json".into(), meta_ino + offset as u64)), _ => return Err(EIO) }
+    }
+    // discussions have no attachments
+    for offset in 0..COURSE_CONTENT_LEN - 1 {
+      let (root, archive) = (ino + 1 + offset as u64, self.inos.len() as u64);
+      self.inos.push(Archive { root, content: Bytes::new(), readers: 0 });
+      match &mut self.inos[root as usize] { ItemList(m, _) => m.push((self.names.archive.into(), archive)), _ => return Err(EIO) }
+    }
+    let mut metas = vec![Vec::new(); COURSE_CONTENT_LEN];
+    macro_rules! handle_items {
+      ($items: expr, $content_fn: expr, $meta_fn: expr, $xattrs_fn: expr, $read_state_fn: expr, $name_fn: expr, $template: expr, $offset: expr) => {
+        for x in $items {
+          metas[$offset - 1].push($meta_fn(&x));
+          let new_ino = self.inos.len() as u64;
+          let (name, xattrs, (read, url)) = ($name_fn(&x, &$template), $xattrs_fn(&x, &course), $read_state_fn(&x, &course));
+          let (m, cs) = $content_fn(x, new_ino + 1, Arc::clone(&client), self.names, self.config.body);
+          self.inos.push(Item(m, xattrs));
+          self.inos.push(ReadState { read, url, client: Arc::clone(&client), item: new_ino });
+          for c in cs { self.inos.push(Content(c)); }
+          match &mut self.inos[ino as usize + $offset] { ItemList(m, _) => push_unique(m, &name, new_ino), _ => return Err(EIO) }
         }
-      }
-      _ => reply.error(EPERM),
+      };
     }
+    for h in hs {
+      metas[0].push(homework_meta(&h));
+      let new_ino = self.inos.len() as u64;
+      let (name, sh, course, homework) =
+        (homework_name(&h, &self.config.naming.homework), h.student_homework_id.clone(), h.course_id.clone(), h.id.clone());
+      let xattrs = homework_xattrs(&h);
+      let (m, cs) = homework_content(h, new_ino + 1, &client, self.names, self.config.body, false);
+      self.inos.push(Item(m, xattrs));
+      self.inos.push(SubmitHomework { student_homework: Arc::new(sh), client: Arc::clone(&client) });
+      self.inos.push(Refresh { parent: new_ino, client: Arc::clone(&client), info: RefreshInfo::Homework { course, homework } });
+      for c in cs { self.inos.push(Content(c)); }
+      match &mut self.inos[ino as usize + 1] { ItemList(m, _) => push_unique(m, &name, new_ino), _ => return Err(EIO) }
+    }
+    handle_items!(ns, notification_content, notification_meta, notification_xattrs, notification_read_state,
+      notification_name, self.config.naming.notification, 2);
+    handle_items!(fs, file_content, file_meta, file_xattrs, file_read_state, file_name, self.config.naming.file, 3);
+    for d in ds {
+      metas[3].push(discussion_meta(&d));
+      let new_ino = self.inos.len() as u64;
+      self.inos.push(Discussion {
+        course_discussion: Arc::new((Arc::clone(&course), d.id)),
+        board: d.board_id,
+        client: Arc::clone(&client),
+        replies: vec![(self.names.refresh.into(), new_ino + 1)],
+      });
+      self.inos.push(Refresh { parent: new_ino, client: Arc::clone(&client), info: RefreshInfo::Discussion });
+      match &mut self.inos[ino as usize + 4] { ItemList(m, _) => push_unique(m, &d.title, new_ino), _ => return Err(EIO) }
+    }
+    // it reflects the state when the course is fetched, `Refresh` in an `Item` doesn't update it
+    for (offset, meta) in metas.into_iter().enumerate() {
+      self.inos[meta_ino as usize + offset] = Content(Content::Data(pretty(&Value::Array(meta)).into()));
+    }
+    Ok(())
   }
 
-  fn create(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, _mode: u32, _flags: u32, reply: ReplyCreate) {
-    info!("create parent={} name={:?}", parent, name);
-    let name = name.to_string_lossy();
-    match &mut self.inos[parent as usize] {
-      Item(m) =>
-        if let Some(ino) = do_lookup(m, &name) {
-          match &self.inos[ino as usize] {
-            SubmitHomework { .. } | Refresh { .. } => reply.created(&TTL, &file_attr(ino, 0), 0, 0, 0),
-            _ => reply.error(EPERM),
+  // fetch discussion replies when it doesn't exist(equivalent to `replies.len() == 1`)
+  fn fetch_discussion_replies(&mut self, ino: u64) -> Result<(), c_int> {
+    self.inherit_owner(ino);
+    let mut new_ino = self.inos.len() as u64;
+    match &mut self.inos[ino as usize] {
+      Discussion { course_discussion, board, client, replies } => {
+        if replies.len() == 1 {
+          let (course, discussion) = (&course_discussion.0, &course_discussion.1);
+          let replies1 = unwrap_or_errno!(self.runtime.request(client.discussion_replies(course, discussion, board)));
+          let (naming, body) = (&self.config.naming, self.config.body);
+          let (reply, sub_reply) = (naming.reply.as_deref().unwrap_or(self.names.reply), naming.sub_reply.as_deref().unwrap_or(self.names.sub_reply));
+          // each reply is followed by its sibling `.html` file, unless it is kept in html format
+          let mut push_name = |name: &str| {
+            push_unique(replies, name, inc!(new_ino));
+            if body != BodyFormat::Html {
+              let html = format!("{}.html", replies.last().unwrap().0);
+              push_unique(replies, &html, inc!(new_ino));
+            }
+          };
+          for (i, r) in replies1.iter().enumerate() {
+            push_name(&render(reply, |k| Some(match k {
+              "floor" => Var::Int(i), "author" => Var::Str(r.author.clone()), "time" => Var::Time(r.publish_time), _ => return None,
+            })));
+            for (j, r) in r.replies.iter().enumerate() {
+              push_name(&render(sub_reply, |k| Some(match k {
+                "floor" => Var::Int(i), "reply" => Var::Int(j), "author" => Var::Str(r.author.clone()), "time" => Var::Time(r.publish_time), _ => return None,
+              })));
+            }
+          }
+          let (course_discussion, client) = (Arc::clone(course_discussion), Arc::clone(client));
+          for r in replies1 {
+            // it can't be a function, because the types of the two `r`s are different
+            macro_rules! push {
+              ($r: expr) => {
+                self.inos.push(DiscussionReply {
+                  course_discussion: Arc::clone(&course_discussion),
+                  id: Arc::new($r.id),
+                  client: Arc::clone(&client),
+                  content: html::convert(&$r.content, body),
+                });
+                if body != BodyFormat::Html { self.inos.push(Content(Content::Data($r.content.into()))); }
+              };
+            }
+            push!(r);
+            for r in r.replies { push!(r); }
           }
-        } else { reply.error(EPERM); }
-      Discussion { .. } => {
-        self.fetch_discussion_replies(parent);
-        match &mut self.inos[parent as usize] {
-          Discussion { replies: m, .. } =>
-            if let Some(ino) = do_lookup(m, &name) {
-              match &self.inos[ino as usize] {
-                DiscussionReply { content, .. } => reply.created(&TTL, &file_attr(ino, content.bytes().len() as u64), 0, 0, 0),
-                Refresh { .. } => reply.created(&TTL, &file_attr(ino, 0), 0, 0, 0),
-                _ => unreachable!(),
-              }
-            } else { reply.error(EPERM); }
-          _ => unreachable!(),
         }
+        Ok(())
+      }
+      _ => Err(ENOTDIR),
+    }
+  }
+}
+
+fn load_config(path: Option<&OsStr>) -> Config {
+  if let Some(path) = path {
+    match Config::load(path.as_ref()) {
+      Ok(x) => x,
+      Err(e) => {
+        eprintln!("无法读取配置文件：{}", e);
+        std::process::exit(1);
       }
-      _ => reply.error(EPERM),
     }
+  } else { Config::default() }
+}
+
+fn usage() -> ! {
+  eprintln!("使用方法：<程序> <挂载点> [配置文件]");
+  eprintln!("　　或：<程序> sync <学号> <目标目录> [配置文件]");
+  eprintln!("　　或：<程序> history <学号> <仓库目录> <间隔分钟数，0 表示只记录一次> [配置文件]");
+  eprintln!("　　或：<程序> webdav <学号> <端口> [配置文件]");
+  eprintln!("　　或：<程序> record <挂载点> <记录文件> [配置文件]");
+  eprintln!("　　或：<程序> replay <挂载点> <记录文件> [配置文件]");
+  eprintln!("　　或：<程序> ls <学号> [路径] [--json] [--config <配置文件>]");
+  eprintln!("　　或：<程序> cat <学号> <路径> [--json] [--config <配置文件>]");
+  eprintln!("　　或：<程序> due <学号> [天数，默认为 7] [--json] [--config <配置文件>]");
+  eprintln!("　　或：<程序> submit <学号> <作业路径> <提交内容> [附件] [--json] [--config <配置文件>]");
+  std::process::exit(1);
+}
+
+fn mount(fs: impl fuse::Filesystem, mountpoint: &OsString) {
+  if let Err(e) = fuse::mount(fs, mountpoint, &[]) {
+    eprintln!("挂载失败：{}", e);
+    std::process::exit(1);
   }
 }
 
 fn main() {
   env_logger::init();
-  let mountpoint = if let Some(x) = std::env::args_os().nth(1) { x } else {
-    eprintln!("使用方法：<程序> <挂载点>");
-    std::process::exit(1);
-  };
-  fuse::mount(LearnFS::new(), mountpoint, &[]).unwrap();
-}
\ No newline at end of file
+  let args = std::env::args_os().collect::<Vec<_>>();
+  match args.get(1) {
+    Some(x) if x == "sync" && args.len() >= 4 => {
+      let config = load_config(args.get(4).map(|x| x.as_os_str()));
+      if let Err(e) = sync::sync(LearnFS::new(config), &args[2].to_string_lossy(), args[3].as_ref()) {
+        eprintln!("同步失败：{}", e);
+        std::process::exit(1);
+      }
+    }
+    Some(x) if x == "history" && args.len() >= 5 => {
+      let interval = args[4].to_string_lossy().parse::<u64>().unwrap_or_else(|_| usage());
+      let config = load_config(args.get(5).map(|x| x.as_os_str()));
+      if let Err(e) = sync::history(config, &args[2].to_string_lossy(), args[3].as_ref(), Duration::from_secs(interval * 60)) {
+        eprintln!("记录历史失败：{}", e);
+        std::process::exit(1);
+      }
+    }
+    Some(x) if x == "webdav" && args.len() >= 4 => {
+      let port = args[3].to_string_lossy().parse::<u16>().unwrap_or_else(|_| usage());
+      let config = load_config(args.get(4).map(|x| x.as_os_str()));
+      if let Err(e) = webdav::serve(LearnFS::new(config), &args[2].to_string_lossy(), port) {
+        eprintln!("WebDAV 服务失败：{}", e);
+        std::process::exit(1);
+      }
+    }
+    // like mounting, but everything fetched is also recorded into a file
+    Some(x) if x == "record" && args.len() >= 4 => {
+      let config = load_config(args.get(4).map(|x| x.as_os_str()));
+      std::env::set_var(fixture::RECORD_ENV, &args[3]);
+      let learn: LearnFS<fixture::Recorder<LearnHelper>> = LearnFS::new(config);
+      mount(learn, &args[2]);
+    }
+    // mount a file written by `record` without accessing the server, any password works
+    Some(x) if x == "replay" && args.len() >= 4 => {
+      let config = load_config(args.get(4).map(|x| x.as_os_str()));
+      std::env::set_var(fixture::FIXTURE_ENV, &args[3]);
+      let learn: LearnFS<fixture::Fixture> = LearnFS::new(config);
+      mount(learn, &args[2]);
+    }
+    Some(x) if ["ls", "cat", "due", "submit"].iter().any(|c| x == *c) => {
+      let rest = args[2..].iter().map(|x| x.to_string_lossy().into_owned()).collect();
+      std::process::exit(cli::run(&x.to_string_lossy(), rest));
+    }
+    Some(mountpoint) => {
+      let config = load_config(args.get(2).map(|x| x.as_os_str()));
+      let api_socket = config.api_socket.clone();
+      let learn: LearnFS = LearnFS::new(config);
+      if let Some(socket) = api_socket {
+        let shared = Arc::new(Mutex::new(learn));
+        let api = Arc::clone(&shared);
+        std::thread::spawn(move || api::serve(api, &socket));
+        mount(fuse_fs::SharedFS(shared), mountpoint);
+      } else {
+        mount(learn, mountpoint);
+      }
+    }
+    None => usage(),
+  }
+}
@@ -0,0 +1,174 @@
+use crate::{LearnFS, InoInfo::*, Content, Xattrs, get_password, config::Config, backend::Backend};
+use std::{collections::HashMap, ffi::OsStr, fs, io::ErrorKind, os::unix::ffi::OsStrExt, path::Path, process::Command, time::{Duration, UNIX_EPOCH}};
+use thu_learn_helper::LearnHelper;
+
+// attachments written by previous runs, keyed by the path relative to the destination
+// the value is the url, the size and upload time on the server (only known for the attachment of a file item),
+// and the size and mtime (in seconds) of the local file when it was written
+type Manifest = HashMap<String, (String, Option<(String, String)>, u64, u64)>;
+
+const MANIFEST: &str = ".learn-sync.json";
+
+// a file in `.git` of a repository created by `history`, it is not tracked, so snapshots never remove it
+const HISTORY_MARKER: &str = ".git/learn-history";
+
+// mirror everything of a student into `dest`, which is the same tree as `<mount point>/<student id>`
+// control files (`提交作业`, `刷新`, `已读`) and `全部文件.zip` are not written
+pub(crate) fn sync(mut learn: LearnFS, student: &str, dest: &Path) -> Result<(), String> {
+  let password = get_password(std::process::id(), learn.names.password_prompt).map_err(|e| e.to_string())?;
+  let uid = learn.uid;
+  let user = learn.login(student, &password, uid).map_err(|e| e.to_string())?;
+  mirror(learn, user, dest).map(|_| ())
+}
+
+// the part of `sync` after logging in, returns the numbers of attachments downloaded and skipped
+pub(crate) fn mirror<B: Backend>(learn: LearnFS<B>, user: u64, dest: &Path) -> Result<(usize, usize), String> {
+  // a missing or broken manifest only makes all attachments downloaded again
+  let old = fs::read(dest.join(MANIFEST)).ok().and_then(|x| serde_json::from_slice(&x).ok()).unwrap_or_default();
+  let mut s = Syncer { fs: learn, dest, old, new: Manifest::new(), downloaded: 0, skipped: 0, attachments: true };
+  let result = s.dir(user, String::new());
+  // the manifest is saved even on failure, so that the next run continues from here
+  let manifest = serde_json::to_vec(&s.new).unwrap();
+  fs::write(dest.join(MANIFEST), manifest).map_err(|e| format!("{}：{}", MANIFEST, e))?;
+  println!("下载 {} 个附件，跳过 {} 个未修改的附件", s.downloaded, s.skipped);
+  result.map(|_| (s.downloaded, s.skipped))
+}
+
+struct Syncer<'a, B = LearnHelper> {
+  fs: LearnFS<B>,
+  dest: &'a Path,
+  old: Manifest,
+  new: Manifest,
+  downloaded: usize,
+  skipped: usize,
+  // false for `history`, which only keeps text files in git
+  attachments: bool,
+}
+
+// commit a snapshot of everything of a student except attachments into the git repository `repo` every `interval`
+// a zero `interval` commits only once; a failed snapshot other than the first one is retried in the next round
+// `repo` must be empty or created by `history` before, because a snapshot replaces what the last one wrote
+pub fn history(config: Config, student: &str, repo: &Path, interval: Duration) -> Result<(), String> {
+  fs::create_dir_all(repo).map_err(|e| e.to_string())?;
+  if !repo.join(HISTORY_MARKER).exists() {
+    if fs::read_dir(repo).map_err(|e| e.to_string())?.next().is_some() {
+      return Err(format!("{} 不是空目录，也不是由 history 创建的", repo.display()));
+    }
+    git(repo, &["init", "-q"])?;
+    fs::write(repo.join(HISTORY_MARKER), "").map_err(|e| e.to_string())?;
+  }
+  let password = get_password(std::process::id(), config.locale.names().password_prompt).map_err(|e| e.to_string())?;
+  for round in 0.. {
+    match snapshot(config.clone(), student, &password, repo) {
+      Ok(()) => {}
+      Err(e) if round == 0 => return Err(e),
+      Err(e) => eprintln!("快照失败：{}", e),
+    }
+    if interval == Duration::from_secs(0) { break; }
+    std::thread::sleep(interval);
+  }
+  Ok(())
+}
+
+// a new `LearnFS` is used for each snapshot, so that everything is fetched again
+fn snapshot(config: Config, student: &str, password: &str, repo: &Path) -> Result<(), String> {
+  let mut learn: LearnFS = LearnFS::new(config);
+  let uid = learn.uid;
+  let user = learn.login(student, password, uid).map_err(|e| e.to_string())?;
+  // the old snapshot is removed first, so that items deleted on the server are also deleted in the commit
+  // only the files tracked by the last snapshot are removed, together with the directories they leave empty
+  let tracked = Command::new("git").arg("-C").arg(repo).args(&["ls-files", "-z"]).output().map_err(|e| format!("git：{}", e))?;
+  if !tracked.status.success() { return Err(format!("git ls-files 失败：{}", tracked.status)); }
+  for file in tracked.stdout.split(|&b| b == 0).filter(|x| !x.is_empty()) {
+    let path = repo.join(OsStr::from_bytes(file));
+    match fs::remove_file(&path) {
+      Err(e) if e.kind() != ErrorKind::NotFound => return Err(format!("{}：{}", path.display(), e)),
+      _ => {}
+    }
+    for dir in path.ancestors().skip(1).take_while(|x| *x != repo) {
+      if fs::remove_dir(dir).is_err() { break; }
+    }
+  }
+  git(repo, &["rm", "-r", "-q", "--cached", "--ignore-unmatch", "."])?;
+  let mut s = Syncer { fs: learn, dest: repo, old: Manifest::new(), new: Manifest::new(), downloaded: 0, skipped: 0, attachments: false };
+  s.dir(user, String::new())?;
+  git(repo, &["add", "-A"])?;
+  // `git commit` fails when nothing changed
+  let status = Command::new("git").arg("-C").arg(repo).args(&["status", "--porcelain"]).output().map_err(|e| e.to_string())?;
+  if !status.stdout.is_empty() {
+    let message = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+    git(repo, &["commit", "-q", "-m", &message])?;
+    println!("已提交快照 {}", message);
+  }
+  Ok(())
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<(), String> {
+  let status = Command::new("git").arg("-C").arg(repo).args(args).status().map_err(|e| format!("git：{}", e))?;
+  if status.success() { Ok(()) } else { Err(format!("git {} 失败：{}", args.join(" "), status)) }
+}
+
+fn mtime(meta: &fs::Metadata) -> Option<u64> {
+  meta.modified().ok()?.duration_since(UNIX_EPOCH).ok().map(|x| x.as_secs())
+}
+
+// the size and upload time of a file item on the server, a teacher may replace its attachment without changing the url
+fn server_version(xattrs: &Xattrs) -> Option<(String, String)> {
+  let get = |k| xattrs.iter().find(|(name, _)| *name == k).map(|(_, v)| v.clone());
+  get("size").zip(get("publish_time"))
+}
+
+impl<B: Backend> Syncer<'_, B> {
+  // `rel` is the path of `ino` relative to `dest`, it is empty or ends with `/`
+  fn dir(&mut self, ino: u64, rel: String) -> Result<(), String> {
+    fs::create_dir_all(self.dest.join(&rel)).map_err(|e| format!("{}：{}", rel, e))?;
+    let children = self.fs.children(ino).map_err(|_| format!("{}：获取失败", rel))?;
+    let version = match &self.fs.inos[ino as usize] { Item(_, x) => server_version(x), _ => None };
+    for (name, x) in children {
+      let rel = rel.clone() + &name;
+      match &self.fs.inos[x as usize] {
+        User { .. } | Semester { .. } | Course { .. } | ItemList(..) | Item(..) | Discussion { .. } => self.dir(x, rel + "/")?,
+        Content(Content::Url(..)) | Content(Content::Downloaded(..)) => if self.attachments { self.attachment(x, rel, version.clone())? },
+        Content(Content::Data(_)) | DiscussionReply { .. } | SemesterGrades { .. } => {
+          self.fs.load(x).map_err(|_| format!("{}：获取失败", rel))?;
+          let data = self.fs.data(x).ok_or_else(|| format!("{}：获取失败", rel))?;
+          let path = self.dest.join(&rel);
+          // small files are compared by content, so that an unchanged file keeps its mtime
+          if fs::read(&path).ok().as_deref() != Some(data) {
+            fs::write(&path, data).map_err(|e| format!("{}：{}", rel, e))?;
+          }
+        }
+        _ => {}
+      }
+    }
+    Ok(())
+  }
+
+  // an attachment is skipped if its url and `version` on the server are unchanged, and the local file still has the recorded size and mtime
+  fn attachment(&mut self, ino: u64, rel: String, version: Option<(String, String)>) -> Result<(), String> {
+    let path = self.dest.join(&rel);
+    let (url, client) = match &self.fs.inos[ino as usize] {
+      Content(Content::Url(url, client)) => (url.clone(), Some(client.clone())),
+      Content(Content::Downloaded(url, _)) => (url.clone(), None),
+      _ => return Err(format!("{}：不是附件", rel)),
+    };
+    if let (Some(old), Ok(meta)) = (self.old.get(&rel), fs::metadata(&path)) {
+      if old.0 == url && old.1 == version && old.2 == meta.len() && Some(old.3) == mtime(&meta) {
+        self.skipped += 1;
+        self.new.insert(rel, old.clone());
+        return Ok(());
+      }
+    }
+    // it is not kept in `LearnFS` like `open` does, otherwise all attachments of a student would stay in memory
+    let data = match (client, &self.fs.inos[ino as usize]) {
+      (Some(client), _) => self.fs.runtime.block_on(client.download(&url, self.fs.runtime.timeout())).map_err(|e| format!("{}：{}", rel, e))?,
+      (None, Content(c)) => c.bytes().to_vec().into(),
+      _ => return Err(format!("{}：不是附件", rel)),
+    };
+    fs::write(&path, &data).map_err(|e| format!("{}：{}", rel, e))?;
+    let meta = fs::metadata(&path).map_err(|e| format!("{}：{}", rel, e))?;
+    self.downloaded += 1;
+    self.new.insert(rel, (url, version, meta.len(), mtime(&meta).unwrap_or(0)));
+    Ok(())
+  }
+}
@@ -0,0 +1,44 @@
+use chrono::NaiveDateTime;
+use std::fmt::Write;
+
+pub enum Var {
+  Str(String),
+  Int(usize),
+  Time(NaiveDateTime),
+}
+
+// `{name}` or `{name:spec}` is replaced by the variable `name`, `{{` and `}}` are escaped braces
+// `spec` is a strftime format for a time, or a width for an integer (a leading `0` pads it with zeros), ignored for a string
+// an unknown variable or an invalid strftime format is kept as is, so that a typo is easy to notice in the file names
+pub fn render(template: &str, var: impl Fn(&str) -> Option<Var>) -> String {
+  let (mut ret, mut rest) = (String::new(), template);
+  while let Some(pos) = rest.find(|c| c == '{' || c == '}') {
+    ret.push_str(&rest[..pos]);
+    rest = &rest[pos..];
+    if rest.starts_with("{{") { ret.push('{'); rest = &rest[2..]; continue; }
+    if rest.starts_with('}') {
+      // `}}` is an escaped brace, a single `}` is kept as is
+      ret.push('}');
+      rest = &rest[if rest.starts_with("}}") { 2 } else { 1 }..];
+      continue;
+    }
+    let end = if let Some(end) = rest.find('}') { end } else { break; };
+    let field = &rest[1..end];
+    let (name, spec) = match field.find(':') { Some(i) => (&field[..i], Some(&field[i + 1..])), None => (field, None) };
+    // writing to a `String` only fails when the strftime format is invalid, then it is kept as is like an unknown variable
+    let mut value = String::new();
+    let written = match (var(name), spec) {
+      (Some(Var::Str(s)), _) => Ok(value = s),
+      (Some(Var::Int(i)), Some(spec)) if spec.starts_with('0') => write!(value, "{:01$}", i, spec.parse::<usize>().unwrap_or(0)),
+      (Some(Var::Int(i)), Some(spec)) => write!(value, "{:1$}", i, spec.parse::<usize>().unwrap_or(0)),
+      (Some(Var::Int(i)), None) => write!(value, "{}", i),
+      (Some(Var::Time(t)), Some(spec)) => write!(value, "{}", t.format(spec)),
+      (Some(Var::Time(t)), None) => write!(value, "{}", t),
+      (None, _) => Err(std::fmt::Error),
+    };
+    ret.push_str(if written.is_ok() { &value } else { &rest[..=end] });
+    rest = &rest[end + 1..];
+  }
+  ret.push_str(rest);
+  ret
+}
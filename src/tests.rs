@@ -0,0 +1,672 @@
+// end-to-end tests of the tree against `tests/fixtures/learn.json`, through the same methods as the commands
+// the tests through a real mount are skipped where FUSE is not available, because they need `/dev/fuse` and a privilege to mount
+use crate::{LearnFS, InoInfo::*, api, webdav, sync, http::{self, Request}, config::Config, fixture::{Fixture, Recorder, FIXTURE_ENV, RECORD_ENV}};
+use crate::backend::{BackendError, BackendResult, BoxError, Executor, classify, errno, fetch};
+use libc::{EACCES, EAGAIN, EFBIG, EIO, ENOENT, ETIMEDOUT};
+use std::{fmt, fs, ffi::CString, io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write}, net::{TcpListener, TcpStream}, path::{Path, PathBuf}, time::Duration};
+use std::{os::unix::ffi::OsStrExt, sync::atomic::{AtomicUsize, Ordering}};
+
+const STUDENT: &str = "2020012345";
+const PASSWORD: &str = "password";
+const OS: &str = "2020-2021-秋/操作系统";
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/learn.json");
+
+fn learn() -> LearnFS<Fixture> {
+  // all tests set the same value, so running them in parallel is fine
+  std::env::set_var(FIXTURE_ENV, FIXTURE);
+  LearnFS::new(Config::default())
+}
+
+fn login() -> (LearnFS<Fixture>, u64) {
+  let mut learn = learn();
+  let uid = learn.uid;
+  let user = learn.login(STUDENT, PASSWORD, uid).unwrap();
+  (learn, user)
+}
+
+fn ls(learn: &mut LearnFS<Fixture>, user: u64, path: &str) -> Vec<String> {
+  let ino = learn.resolve(user, path).unwrap();
+  learn.children(ino).unwrap().into_iter().map(|(name, _)| name).collect()
+}
+
+fn cat(learn: &mut LearnFS<Fixture>, user: u64, path: &str) -> String {
+  let ino = learn.resolve(user, path).unwrap();
+  assert_eq!(learn.load(ino), Ok(()), "failed to load {}", path);
+  String::from_utf8(learn.data(ino).unwrap().to_vec()).unwrap()
+}
+
+// the status and the JSON body of an API request from `uid`
+fn api(learn: &mut LearnFS<Fixture>, uid: u32, method: &str, path: &str, body: &str) -> (&'static str, serde_json::Value) {
+  let req = Request { method: method.to_owned(), path: path.to_owned(), depth: None, authorization: None, body: body.as_bytes().to_vec() };
+  let (status, _, body) = api::respond(learn, uid, &req);
+  (status, serde_json::from_slice(&body).unwrap())
+}
+
+// the status and the body of a WebDAV request to the tree of `user`
+fn webdav(learn: &mut LearnFS<Fixture>, user: u64, method: &str, path: &str, body: &[u8]) -> (&'static str, String) {
+  let req = Request { method: method.to_owned(), path: path.to_owned(), depth: Some("1".to_owned()), authorization: None, body: body.to_vec() };
+  let (status, _, body) = webdav::respond(learn, user, &req);
+  (status, String::from_utf8(body).unwrap())
+}
+
+// the tree of `login()` mounted on a temporary directory, unmounted and removed when dropped
+struct Mount(Option<fuse::BackgroundSession<'static>>, PathBuf);
+
+impl Drop for Mount {
+  fn drop(&mut self) {
+    self.0.take();
+    let _ = fs::remove_dir(&self.1);
+  }
+}
+
+// `None` where FUSE is not available, then the test is skipped
+fn mount() -> Option<Mount> {
+  static COUNT: AtomicUsize = AtomicUsize::new(0);
+  if !Path::new("/dev/fuse").exists() {
+    eprintln!("skipped, /dev/fuse is missing");
+    return None;
+  }
+  let (learn, _) = login();
+  let dir = std::env::temp_dir().join(format!("learn-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+  fs::create_dir_all(&dir).unwrap();
+  // it can still fail without a privilege to mount, e.g. in a container without `fusermount`
+  match unsafe { fuse::spawn_mount(learn, &dir, &[]) } {
+    Ok(session) => Some(Mount(Some(session), dir)),
+    Err(e) => {
+      eprintln!("skipped, failed to mount: {}", e);
+      let _ = fs::remove_dir(&dir);
+      None
+    }
+  }
+}
+
+fn getxattr(path: &Path, name: &str) -> Result<String, i32> {
+  let (path, name) = (CString::new(path.as_os_str().as_bytes()).unwrap(), CString::new(name).unwrap());
+  let mut buf = [0u8; 256];
+  let len = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+  if len < 0 { return Err(std::io::Error::last_os_error().raw_os_error().unwrap()); }
+  Ok(String::from_utf8_lossy(&buf[..len as usize]).into_owned())
+}
+
+// a server on localhost calling `respond` with the path of each request and the connection to answer it; returns its url
+fn fake_server(respond: impl Fn(&str, &mut TcpStream) + Send + 'static) -> String {
+  let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+  let url = format!("http://{}", listener.local_addr().unwrap());
+  std::thread::spawn(move || for mut s in listener.incoming().filter_map(Result::ok) {
+    let mut r = BufReader::new(s.try_clone().unwrap());
+    let mut line = String::new();
+    r.read_line(&mut line).unwrap();
+    // the headers end with an empty line, none of them is needed
+    loop {
+      let mut header = String::new();
+      if r.read_line(&mut header).unwrap_or(0) <= 2 { break; }
+    }
+    respond(line.split_whitespace().nth(1).unwrap_or("/"), &mut s);
+  });
+  url
+}
+
+fn reply(s: &mut TcpStream, status: u16, body: &str) {
+  let _ = write!(s, "HTTP/1.1 {} Fake\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+}
+
+fn fixture(learn: &LearnFS<Fixture>, user: u64) -> &Fixture {
+  match &learn.inos[user as usize] { User { client, .. } => &**client, _ => panic!("not a user") }
+}
+
+#[test]
+fn login_fails() {
+  let mut learn = learn();
+  let uid = learn.uid;
+  assert_eq!(errno(&learn.login(STUDENT, "wrong", uid).unwrap_err()), libc::EACCES);
+  assert_eq!(errno(&learn.login("2020000000", PASSWORD, uid).unwrap_err()), libc::EACCES);
+  assert!(learn.children(1).unwrap().is_empty());
+}
+
+#[test]
+fn tree() {
+  let (mut learn, user) = login();
+  assert_eq!(learn.children(1).unwrap(), vec![(STUDENT.to_owned(), user)]);
+  assert_eq!(ls(&mut learn, user, ""), ["2020-2021-秋", "2019-2020-春"]);
+  assert_eq!(ls(&mut learn, user, "2020-2021-秋"), ["成绩汇总.csv", "操作系统"]);
+  assert_eq!(ls(&mut learn, user, OS), ["作业", "通知", "文件", "讨论", "课程信息", "课程信息.json", "成绩汇总.csv", "全部文件.zip"]);
+  assert_eq!(ls(&mut learn, user, &format!("{}/作业", OS)), ["meta.json", "全部文件.zip", "lab1", "lab0"]);
+  assert_eq!(ls(&mut learn, user, &format!("{}/讨论", OS)), ["meta.json", "lab1 问题"]);
+  assert_eq!(learn.resolve(user, "2020-2021-秋/不存在"), Err(libc::ENOENT));
+  let info = learn.resolve(user, &format!("{}/课程信息", OS)).unwrap();
+  assert_eq!(learn.children(info), Err(libc::ENOTDIR));
+}
+
+#[test]
+fn files() {
+  let (mut learn, user) = login();
+  assert!(cat(&mut learn, user, &format!("{}/课程信息", OS)).contains("英文名: Operating Systems\n"));
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/描述", OS)).trim(), "实现 系统调用");
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/附件：lab1.pdf", OS)), "lab1 handout");
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab0/提交内容", OS)), "done");
+  let meta: serde_json::Value = serde_json::from_str(&cat(&mut learn, user, &format!("{}/作业/lab1/meta.json", OS))).unwrap();
+  assert_eq!(meta["deadline"], "2020-10-04T23:59:00");
+  assert_eq!(meta["submitted"], false);
+  let grades = cat(&mut learn, user, "2020-2021-秋/成绩汇总.csv");
+  assert!(grades.lines().any(|x| x.starts_with("操作系统,lab0,") && x.contains(",95,")), "{}", grades);
+  let zip = learn.resolve(user, &format!("{}/全部文件.zip", OS)).unwrap();
+  assert_eq!(learn.load(zip), Ok(()));
+  assert!(learn.data(zip).unwrap().starts_with(b"PK"));
+}
+
+#[test]
+fn submit_and_refresh() {
+  let (mut learn, user) = login();
+  let lab1 = learn.resolve(user, &format!("{}/作业/lab1", OS)).unwrap();
+  learn.submit(lab1, "my answer".to_owned(), Some(("answer.txt", b"42".to_vec()))).unwrap();
+  // nothing changes until refreshed
+  assert!(!ls(&mut learn, user, &format!("{}/作业/lab1", OS)).iter().any(|x| x == "提交内容"));
+  let refresh = learn.resolve(lab1, "刷新").unwrap();
+  learn.refresh(refresh).unwrap();
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/提交内容", OS)), "my answer");
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/提交附件：answer.txt", OS)), "42");
+  // writing to `提交作业` submits the same way
+  let submit = learn.resolve(lab1, "提交作业").unwrap();
+  learn.submit(submit, "new answer".to_owned(), None).unwrap();
+  learn.refresh(refresh).unwrap();
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/提交内容", OS)), "new answer");
+  // a notification is not a homework
+  let notification = learn.resolve(user, &format!("{}/通知/课程安排", OS)).unwrap();
+  assert_eq!(learn.submit(notification, String::new(), None), Err(libc::ENOTDIR));
+}
+
+#[test]
+fn archive() {
+  let (mut learn, user) = login();
+  let zip = learn.resolve(user, &format!("{}/全部文件.zip", OS)).unwrap();
+  learn.load(zip).unwrap();
+  let data = learn.data(zip).unwrap();
+  // entries are stored without compressing
+  let contains = |x: &[u8]| data.windows(x.len()).any(|w| w == x);
+  assert!(contains("作业/lab1/附件：lab1.pdf".as_bytes()) && contains(b"lab1 handout"));
+  // the attachments downloaded for it are not kept, and it is dropped by `unload`
+  let pdf = learn.resolve(user, &format!("{}/作业/lab1/附件：lab1.pdf", OS)).unwrap();
+  assert!(matches!(learn.inos[pdf as usize], Content(crate::Content::Url(..))));
+  learn.unload(zip);
+  assert_eq!(learn.data(zip), Some(&[][..]));
+}
+
+#[test]
+fn reply_and_refresh() {
+  let (mut learn, user) = login();
+  let path = format!("{}/讨论/lab1 问题", OS);
+  let discussion = learn.resolve(user, &path).unwrap();
+  let before = ls(&mut learn, user, &path);
+  // `刷新`, and each of the two replies with its `.html` file
+  assert_eq!(before.len(), 5);
+  assert!(before[1].starts_with("0楼-张三-") && before[3].starts_with("0楼-回复0-李四-"));
+  assert_eq!(cat(&mut learn, user, &format!("{}/{}", path, before[3])), "用 gdb");
+  learn.reply_discussion(discussion, None, "<p>谢谢</p>".to_owned(), None).unwrap();
+  // replying to a reply by its name in the discussion, or by writing to it
+  let floor0 = learn.resolve(discussion, &before[1]).unwrap();
+  learn.reply_discussion(floor0, None, "不客气".to_owned(), None).unwrap();
+  assert_eq!(learn.reply_discussion(discussion, Some("不存在"), String::new(), None), Err(libc::ENOENT));
+  let refresh = learn.resolve(discussion, "刷新").unwrap();
+  learn.refresh(refresh).unwrap();
+  let after = ls(&mut learn, user, &path);
+  assert_eq!(after.len(), 9);
+  assert!(after[5].starts_with(&format!("0楼-回复1-{}-", STUDENT)));
+  assert!(after[7].starts_with(&format!("1楼-{}-", STUDENT)));
+  assert_eq!(cat(&mut learn, user, &format!("{}/{}", path, after[7])).trim(), "谢谢");
+}
+
+#[test]
+fn read_state() {
+  let (mut learn, user) = login();
+  let read = learn.resolve(user, &format!("{}/通知/课程安排/已读", OS)).unwrap();
+  assert_eq!(learn.data(read), Some("否".as_bytes()));
+  learn.set_read(read, true).unwrap();
+  assert_eq!(learn.data(read), Some("是".as_bytes()));
+  assert_eq!(learn.set_read(read, false), Err(libc::EPERM));
+  let file = learn.resolve(user, &format!("{}/文件/slides", OS)).unwrap();
+  assert_eq!(learn.set_item_read(file, true), Err(libc::ENOTSUP));
+  let file_read = learn.resolve(file, "已读").unwrap();
+  learn.set_read(file_read, true).unwrap();
+  // the urls visited by the web UI, which mark the items as read: the detail page of a notification, and the attachment of a file
+  let attachment = learn.resolve(file, "slides.pdf").unwrap();
+  let url = match &learn.inos[attachment as usize] { Content(crate::Content::Url(url, _)) => url.clone(), _ => panic!("not an attachment") };
+  let visited = fixture(&learn, user).visited.lock().unwrap().clone();
+  assert_eq!(visited, ["https://learn.tsinghua.edu.cn/f/wlxt/kcgg/wlkc_ggb/student/beforeViewXs?wlkcid=os&id=n1".to_owned(), url]);
+  let notification = learn.resolve(user, &format!("{}/通知/课程安排", OS)).unwrap();
+  assert_eq!(learn.set_item_read(notification, true), Ok(()));
+  assert_eq!(learn.set_item_read(read, true), Err(libc::ENOTSUP));
+}
+
+#[test]
+fn read_state_expired() {
+  let (mut learn, user) = login();
+  let read = learn.resolve(user, &format!("{}/通知/课程安排/已读", OS)).unwrap();
+  let page = "https://learn.tsinghua.edu.cn/f/wlxt/kcgg/wlkc_ggb/student/beforeViewXs?wlkcid=os&id=n1";
+  let login_page = "<html><form action=\"https://id.tsinghua.edu.cn/do/off/ui/auth/login/post/0/0?/login.do\"></form></html>";
+  fixture(&learn, user).data.lock().unwrap()["downloads"][page] = login_page.into();
+  // the session expired, so the item is not marked as read
+  assert_eq!(learn.set_read(read, true), Err(EACCES));
+  assert_eq!(learn.data(read), Some("否".as_bytes()));
+}
+
+#[test]
+fn owners() {
+  let mut learn = learn();
+  let user = learn.login(STUDENT, PASSWORD, 1000).unwrap();
+  let course = learn.resolve(user, OS).unwrap();
+  assert!(learn.accessible(1000, user) && learn.accessible(1000, course));
+  assert!(!learn.accessible(1001, user) && !learn.accessible(1001, course));
+  assert!(learn.accessible(1001, 1));
+}
+
+#[test]
+fn owners_of_fetched() {
+  let mut learn = learn();
+  let first = learn.login(STUDENT, PASSWORD, 1000).unwrap();
+  let second = learn.login(STUDENT, PASSWORD, 1001).unwrap();
+  // fetched after `second` logs in, but for `first`
+  let description = learn.resolve(first, &format!("{}/作业/lab1/描述", OS)).unwrap();
+  assert!(learn.accessible(1000, description) && !learn.accessible(1001, description));
+  // fetched after listing the root, which belongs to nobody
+  learn.children(1).unwrap();
+  let reply = learn.resolve(second, &format!("{}/讨论/lab1 问题", OS)).unwrap();
+  let reply = learn.children(reply).unwrap()[1].1;
+  assert!(learn.accessible(1001, reply) && !learn.accessible(1000, reply));
+}
+
+#[test]
+fn timeout() {
+  let mut runtime = Executor::new(Some(1));
+  let e = runtime.request(futures::future::pending::<BackendResult<()>>()).unwrap_err();
+  assert_eq!(errno(&e), libc::ETIMEDOUT);
+}
+
+#[test]
+fn upload_timeout() {
+  let mut runtime = Executor::new(Some(1));
+  assert_eq!(runtime.upload_limit(0), Some(Duration::from_secs(1)));
+  assert_eq!(runtime.upload_limit(1 << 20), Some(Duration::from_secs(65)));
+  assert_eq!(Executor::new(Some(0)).upload_limit(1 << 20), None);
+  let e = runtime.upload(100, futures::future::pending::<BackendResult<()>>()).unwrap_err();
+  assert_eq!(errno(&e), ETIMEDOUT);
+}
+
+#[test]
+fn download_timeout() {
+  let mut runtime = Executor::new(Some(1));
+  let client = reqwest::Client::new();
+  // each of the 3 bytes of the body comes after a gap of `<path>` milliseconds
+  let url = fake_server(|path, s| {
+    let gap = Duration::from_millis(path[1..].parse().unwrap());
+    let _ = write!(s, "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\n");
+    for b in b"abc" {
+      std::thread::sleep(gap);
+      let _ = s.write_all(&[*b]).and_then(|_| s.flush());
+    }
+  });
+  let timeout = runtime.timeout();
+  // longer than the timeout in total, but each part comes in time
+  assert_eq!(runtime.block_on(fetch(&client, &format!("{}/600", url), timeout)).map_err(|e| errno(&e)), Ok("abc".into()));
+  assert_eq!(runtime.block_on(fetch(&client, &format!("{}/1500", url), timeout)).map_err(|e| errno(&e)), Err(ETIMEDOUT));
+}
+
+#[test]
+fn api_owners() {
+  let mut learn = learn();
+  learn.login(STUDENT, PASSWORD, 1000).unwrap();
+  let info = format!("/{}/{}/课程信息", STUDENT, OS);
+  assert_eq!(api(&mut learn, 1000, "GET", &info, "").0, "200 OK");
+  assert_eq!(api(&mut learn, 0, "GET", &info, "").0, "200 OK");
+  assert_eq!(api(&mut learn, 1001, "GET", &info, "").0, "403 Forbidden");
+  let submit = format!("/{}/{}/作业/lab1/submit", STUDENT, OS);
+  assert_eq!(api(&mut learn, 1001, "POST", &submit, r#"{"content": "not mine"}"#).0, "403 Forbidden");
+  // other students are not even listed
+  let (status, root) = api(&mut learn, 1001, "GET", "/", "");
+  assert_eq!((status, root["entries"].as_array().unwrap().len()), ("200 OK", 0));
+  assert_eq!(api(&mut learn, 1000, "GET", "/", "").1["entries"][0]["name"], STUDENT);
+}
+
+#[test]
+fn http_errors() {
+  let mut runtime = Executor::new(Some(0));
+  let client = reqwest::Client::new();
+  let url = fake_server(|path, s| reply(s, path[1..].parse().unwrap(), "body"));
+  let mut get = |client: &reqwest::Client, url: &str| runtime.block_on(fetch(client, url, None)).map_err(|e| errno(&e));
+  assert_eq!(get(&client, &format!("{}/200", url)), Ok("body".into()));
+  for &(status, e) in &[(401, EACCES), (403, EACCES), (404, ENOENT), (413, EFBIG), (500, EIO), (502, EIO)] {
+    assert_eq!(get(&client, &format!("{}/{}", url, status)), Err(e), "{}", status);
+  }
+  // nothing listens on the port after the listener is dropped
+  let closed = format!("http://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+  assert_eq!(get(&client, &closed), Err(EAGAIN));
+  let slow = fake_server(|_, s| {
+    std::thread::sleep(Duration::from_secs(2));
+    reply(s, 200, "");
+  });
+  let impatient = reqwest::Client::builder().timeout(Duration::from_millis(100)).build().unwrap();
+  assert_eq!(get(&impatient, &slow), Err(ETIMEDOUT));
+}
+
+// an error of `thu_learn_helper` with the error of `serde_json` as its source
+#[derive(Debug)]
+struct Wrapped(serde_json::Error);
+
+impl fmt::Display for Wrapped {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "failed to parse: {}", self.0) }
+}
+
+impl std::error::Error for Wrapped {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> { Some(&self.0) }
+}
+
+#[test]
+fn json_errors() {
+  let json = |s: &str| serde_json::from_str::<Vec<u32>>(s).unwrap_err();
+  // the login page of an expired session
+  assert_eq!(classify(&json("<html></html>")), Some(BackendError::Auth));
+  assert_eq!(classify(&json("\n<!DOCTYPE html>")), Some(BackendError::Auth));
+  assert_eq!(classify(&Wrapped(json("<html></html>"))), Some(BackendError::Auth));
+  // a changed format, or a broken response
+  assert_eq!(classify(&json(r#"{"a": 1}"#)), None);
+  assert_eq!(classify(&json("[1, 2")), None);
+  assert_eq!(classify(&Wrapped(json(r#"["a"]"#))), None);
+  assert_eq!(classify(&*BoxError::from("something else")), None);
+}
+
+#[test]
+fn webdav_frontend() {
+  let (mut learn, user) = login();
+  let (status, body) = webdav(&mut learn, user, "PROPFIND", OS, b"");
+  // the course itself and its 8 children
+  assert_eq!((status, body.matches("<D:response>").count()), ("207 Multi-Status", 9));
+  assert!(body.contains("<D:collection/>") && body.contains("<D:getcontentlength>"));
+  let (status, body) = webdav(&mut learn, user, "GET", &format!("{}/作业/lab1/附件：lab1.pdf", OS), b"");
+  assert_eq!((status, body.as_str()), ("200 OK", "lab1 handout"));
+  assert!(webdav(&mut learn, user, "GET", &format!("{}/作业", OS), b"").1.contains(">lab1</a>"));
+  assert_eq!(webdav(&mut learn, user, "GET", &format!("{}/不存在", OS), b"").0, "404 Not Found");
+  assert_eq!(webdav(&mut learn, user, "PUT", &format!("{}/作业/lab1/提交作业", OS), b"my answer").0, "204 No Content");
+  let refresh = learn.resolve(user, &format!("{}/作业/lab1/刷新", OS)).unwrap();
+  learn.refresh(refresh).unwrap();
+  assert_eq!(cat(&mut learn, user, &format!("{}/作业/lab1/提交内容", OS)), "my answer");
+  assert_eq!(webdav(&mut learn, user, "PUT", &format!("{}/通知/课程安排/提交作业", OS), b"").0, "404 Not Found");
+  // nothing else can be written, and nothing is submitted
+  assert_eq!(webdav(&mut learn, user, "PUT", &format!("{}/作业/lab1/report.pdf", OS), b"%PDF").0, "403 Forbidden");
+  learn.refresh(refresh).unwrap();
+  assert!(!ls(&mut learn, user, &format!("{}/作业/lab1", OS)).iter().any(|x| x.contains("report.pdf")));
+  assert_eq!(webdav(&mut learn, user, "DELETE", OS, b"").0, "405 Method Not Allowed");
+}
+
+#[test]
+fn mounted_tree() {
+  let mount = match mount() { Some(x) => x, None => return };
+  let os = mount.1.join(STUDENT).join(OS);
+  let names = fs::read_dir(&os).unwrap().map(|x| x.unwrap().file_name().into_string().unwrap()).collect::<Vec<_>>();
+  assert_eq!(names, ["作业", "通知", "文件", "讨论", "课程信息", "课程信息.json", "成绩汇总.csv", "全部文件.zip"]);
+  assert_eq!(fs::read_to_string(os.join("作业/lab1/描述")).unwrap().trim(), "实现 系统调用");
+  assert_eq!(fs::read(os.join("作业/lab1/附件：lab1.pdf")).unwrap(), b"lab1 handout");
+  assert!(fs::metadata(os.join("作业/lab1")).unwrap().is_dir());
+  assert_eq!(fs::metadata(os.join("不存在")).unwrap_err().raw_os_error(), Some(ENOENT));
+  assert_eq!(getxattr(&os.join("通知/课程安排"), "user.learn.id"), Ok("n1".to_owned()));
+  assert!(fs::read(os.join("全部文件.zip")).unwrap().starts_with(b"PK"));
+  // reading past the end gives nothing instead of failing
+  let mut f = fs::File::open(os.join("课程信息")).unwrap();
+  f.seek(SeekFrom::Start(1 << 20)).unwrap();
+  assert_eq!(f.read(&mut [0; 16]).unwrap(), 0);
+}
+
+#[test]
+fn mounted_writes() {
+  let mount = match mount() { Some(x) => x, None => return };
+  let lab1 = mount.1.join(STUDENT).join(OS).join("作业/lab1");
+  fs::write(lab1.join("提交作业"), "my answer").unwrap();
+  fs::write(lab1.join("刷新"), "1").unwrap();
+  assert_eq!(fs::read_to_string(lab1.join("提交内容")).unwrap(), "my answer");
+  // the write fails if the attachment can't be read, instead of failing silently later
+  let e = fs::write(lab1.join("提交作业"), "FILE=不存在.txt my answer").unwrap_err();
+  assert_eq!(e.raw_os_error(), Some(ENOENT));
+  let read = mount.1.join(STUDENT).join(OS).join("通知/课程安排/已读");
+  assert_eq!(fs::read_to_string(&read).unwrap(), "否");
+  fs::write(&read, "是").unwrap();
+  assert_eq!(fs::read_to_string(&read).unwrap(), "是");
+  assert_eq!(fs::write(&read, "否").unwrap_err().raw_os_error(), Some(libc::EPERM));
+}
+
+#[test]
+fn sync_changed() {
+  let dest = std::env::temp_dir().join(format!("learn-sync-{}", std::process::id()));
+  let (learn, user) = login();
+  let (downloaded, skipped) = sync::mirror(learn, user, &dest).unwrap();
+  assert!(downloaded >= 2 && skipped == 0);
+  assert_eq!(fs::read_to_string(dest.join(OS).join("作业/lab1/附件：lab1.pdf")).unwrap(), "lab1 handout");
+  let (learn, user) = login();
+  assert_eq!(sync::mirror(learn, user, &dest), Ok((0, downloaded)));
+  // a file replaced on the server keeps its url, but not its size
+  let (learn, user) = login();
+  fixture(&learn, user).data.lock().unwrap()["files"]["os"][0]["size"] = serde_json::json!("2M");
+  assert_eq!(sync::mirror(learn, user, &dest), Ok((1, downloaded - 1)));
+  fs::remove_dir_all(&dest).unwrap();
+}
+
+#[test]
+fn http_body_limit() {
+  let read = |req: String| http::read_request(&mut BufReader::new(Cursor::new(req.into_bytes()))).unwrap().map(|x| x.body);
+  assert_eq!(read("PUT /x HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc".to_owned()), Ok(b"abc".to_vec()));
+  // rejected before anything is allocated for it
+  assert_eq!(read(format!("PUT /x HTTP/1.1\r\nContent-Length: {}\r\n\r\n", http::MAX_BODY + 1)), Err("413 Payload Too Large"));
+  assert_eq!(read(format!("PUT /x HTTP/1.1\r\nContent-Length: {}\r\n\r\n", usize::MAX)), Err("413 Payload Too Large"));
+  assert_eq!(read("PUT /x HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_owned()), Err("411 Length Required"));
+}
+
+#[test]
+fn record() {
+  let path = std::env::temp_dir().join(format!("learn-record-{}.json", std::process::id()));
+  // no other test records
+  std::env::set_var(RECORD_ENV, &path);
+  std::env::set_var(FIXTURE_ENV, FIXTURE);
+  let mut learn: LearnFS<Recorder<Fixture>> = LearnFS::new(Config::default());
+  let uid = learn.uid;
+  let user = learn.login(STUDENT, PASSWORD, uid).unwrap();
+  for path in &["作业/lab1/附件：lab1.pdf", "作业/lab0/提交内容", "通知/课程安排/已读", "讨论/lab1 问题"] {
+    let ino = learn.resolve(user, &format!("{}/{}", OS, path)).unwrap();
+    learn.load(ino).unwrap();
+  }
+  // already written, without waiting for the student to log out
+  let text = fs::read_to_string(&path).unwrap();
+  drop(learn);
+  fs::remove_file(&path).unwrap();
+  for personal in &[STUDENT, "向勇", "张三", "助教", "lab1 handout", "done", "用 gdb"] {
+    assert!(!text.contains(personal), "{} is recorded", personal);
+  }
+  let recorded: serde_json::Value = serde_json::from_str(&text).unwrap();
+  let data = &recorded["*"];
+  assert_eq!(data["courses"]["2020-2021-1"][0]["name"], "操作系统");
+  assert_eq!(data["downloads"]["https://learn.tsinghua.edu.cn/b/wlxt/kczy/zy/student/downloadFile/lab1"], "redacted, 12 bytes");
+  // still in the form `Fixture` reads
+  let hs: Vec<thu_learn_helper::types::Homework> = serde_json::from_value(data["homework"]["os"].clone()).unwrap();
+  assert_eq!(hs[1].title, "lab0");
+}
+
+#[test]
+fn http_auth() {
+  assert_eq!(http::base64(b""), "");
+  assert_eq!(http::base64(b"f"), "Zg==");
+  assert_eq!(http::base64(b"fo"), "Zm8=");
+  assert_eq!(http::base64(b"foo"), "Zm9v");
+  assert_eq!(http::base64("学".as_bytes()), "5a2m");
+  let req = |authorization: Option<&str>| Request {
+    method: "GET".to_owned(), path: "/".to_owned(), depth: None, authorization: authorization.map(str::to_owned), body: Vec::new(),
+  };
+  // base64 of "learn:secret"
+  assert!(http::authorized(&req(Some("Basic bGVhcm46c2VjcmV0")), "secret"));
+  assert!(!http::authorized(&req(Some("Basic bGVhcm46c2VjcmV1")), "secret"));
+  assert!(!http::authorized(&req(Some("Basic bGVhcm46c2VjcmV0")), "other"));
+  assert!(!http::authorized(&req(None), "secret"));
+}
+
+#[test]
+fn api_attachment() {
+  let mut learn = learn();
+  let uid = learn.uid;
+  learn.login(STUDENT, PASSWORD, uid).unwrap();
+  let dir = std::env::temp_dir().join(format!("learn-attachment-{}", std::process::id()));
+  fs::create_dir_all(&dir).unwrap();
+  let (file, link, fifo) = (dir.join("answer.txt"), dir.join("link.txt"), dir.join("fifo"));
+  fs::write(&file, "42").unwrap();
+  let _ = fs::remove_file(&link);
+  std::os::unix::fs::symlink(&file, &link).unwrap();
+  let path = CString::new(fifo.as_os_str().as_bytes()).unwrap();
+  assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+  let submit = format!("/{}/{}/作业/lab1/submit", STUDENT, OS);
+  let body = |x: &Path| serde_json::json!({ "content": "see attachment", "attachment": x }).to_string();
+  // a symlink could be swapped for another one between checking and reading it, so it is never followed
+  assert_eq!(api(&mut learn, uid, "POST", &submit, &body(&link)).0, "403 Forbidden");
+  assert_eq!(api(&mut learn, uid, "POST", &submit, &body(&fifo)).0, "400 Bad Request");
+  assert_eq!(api(&mut learn, uid, "POST", &submit, &body(&dir.join("none"))).0, "404 Not Found");
+  assert_eq!(api(&mut learn, uid, "POST", &submit, &body(&file)).0, "200 OK");
+  fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn html_convert() {
+  use crate::html::{convert, BodyFormat::{Html, Markdown, Text}};
+  let list = "<ol><li>a<ul><li>b</li><li>c</li></ul></li><li>d</li></ol>";
+  // (html, text, Markdown)
+  let cases = [
+    // a `<` without `>` is text, an empty tag is dropped
+    ("a <b c", "a <b c\n", "a <b c\n"),
+    ("x</>y", "xy\n", "xy\n"),
+    ("a<!-- b -->c", "ac\n", "ac\n"),
+    ("a<!-- b", "a\n", "a\n"),
+    ("a<script>if (x < y) {}</script>b", "ab\n", "ab\n"),
+    ("a<SCRIPT>x</SCRIPT>b<style>p {}</style>", "ab\n", "ab\n"),
+    ("<script>never closed", "", ""),
+    ("&#65;&#x42; &lt;p&gt;", "AB <p>\n", "AB <p>\n"),
+    (list, "1. a\n  - b\n  - c\n2. d\n", "1. a\n  - b\n  - c\n2. d\n"),
+    ("见<a href=\"https://x.cn\">这里</a>。", "见这里 (https://x.cn)。\n", "见[这里](https://x.cn)。\n"),
+    ("<a href='https://x.cn'>https://x.cn</a>", "https://x.cn\n", "[https://x.cn](https://x.cn)\n"),
+    ("<a href=\"javascript:void(0)\">点击</a>", "点击\n", "点击\n"),
+    ("<p>a*b_c [d] `e` \\</p><b>f</b>", "a*b_c [d] `e` \\\n\nf\n", "a\\*b\\_c \\[d\\] \\`e\\` \\\\\n\n**f**\n"),
+  ];
+  for &(html, text, markdown) in &cases {
+    assert_eq!(convert(html, Text), text, "{}", html);
+    assert_eq!(convert(html, Markdown), markdown, "{}", html);
+    assert_eq!(convert(html, Html), html);
+  }
+}
+
+#[test]
+fn html_entities() {
+  use crate::html::decode_entities;
+  let cases = [
+    ("&#65;&#x42;&#X43;", "ABC"),
+    ("&#128512;", "😀"),
+    ("&lt;&amp;amp;&nbsp;&hellip;", "<&amp; …"),
+    // invalid or unknown ones are kept as they are
+    ("&unknown; &#xZZ; &#xD800; &#; & x", "&unknown; &#xZZ; &#xD800; &#; & x"),
+    ("&averyveryverylongname;", "&averyveryverylongname;"),
+  ];
+  for &(s, decoded) in &cases { assert_eq!(decode_entities(s), decoded, "{}", s); }
+}
+
+#[test]
+fn listing() {
+  use crate::config::SortBy;
+  let homework = format!("{}/作业", OS);
+  let items = |names: Vec<String>| names.into_iter().filter(|x| x.starts_with("lab")).collect::<Vec<_>>();
+  std::env::set_var(FIXTURE_ENV, FIXTURE);
+  let mut config = Config::default();
+  config.listing.homework.sort = SortBy::Title;
+  let mut learn: LearnFS<Fixture> = LearnFS::new(config.clone());
+  let uid = learn.uid;
+  let user = learn.login(STUDENT, PASSWORD, uid).unwrap();
+  // `children` keeps the order of the server, which is how `lookup` and `sync` see it
+  assert_eq!(items(ls(&mut learn, user, &homework)), ["lab1", "lab0"]);
+  let ino = learn.resolve(user, &homework).unwrap();
+  assert_eq!(items(learn.listing(ino).unwrap().into_iter().map(|(name, _)| name).collect()), ["lab0", "lab1"]);
+  config.listing.homework.hide_submitted = true;
+  let mut learn: LearnFS<Fixture> = LearnFS::new(config);
+  let user = learn.login(STUDENT, PASSWORD, uid).unwrap();
+  let ino = learn.resolve(user, &homework).unwrap();
+  assert_eq!(items(learn.listing(ino).unwrap().into_iter().map(|(name, _)| name).collect()), ["lab1"]);
+  // the same for the other frontends, and a hidden entry is still found by name
+  let (_, body) = api(&mut learn, uid, "GET", &format!("/{}/{}", STUDENT, homework), "");
+  let names = body["entries"].as_array().unwrap().iter().map(|x| x["name"].as_str().unwrap().to_owned()).collect();
+  assert_eq!(items(names), ["lab1"]);
+  let (_, body) = webdav(&mut learn, user, "PROPFIND", &homework, b"");
+  assert!(body.contains("/lab1/") && !body.contains("/lab0/"));
+  assert_eq!(api(&mut learn, uid, "GET", &format!("/{}/{}/lab0/描述", STUDENT, homework), "").0, "200 OK");
+  assert_eq!(cat(&mut learn, user, &format!("{}/lab0/提交内容", homework)).trim(), "done");
+}
+
+#[test]
+fn template_render() {
+  use crate::template::{render, Var};
+  let var = |name: &str| match name {
+    "title" => Some(Var::Str("lab1".to_owned())),
+    "floor" => Some(Var::Int(7)),
+    "deadline" => Some(Var::Time(chrono::NaiveDateTime::parse_from_str("2020-10-04 23:59:00", "%Y-%m-%d %H:%M:%S").unwrap())),
+    _ => None,
+  };
+  let cases = [
+    ("{floor:03}-{title}", "007-lab1"),
+    ("{floor:3}|{floor}", "  7|7"),
+    ("{deadline:%m%d}-{title}", "1004-lab1"),
+    ("{deadline}", "2020-10-04 23:59:00"),
+    ("{title:%m}", "lab1"),
+    ("{{title}} {title}", "{title} lab1"),
+    ("a}}b}c", "a}b}c"),
+    // kept as is
+    ("{nope}-{title}", "{nope}-lab1"),
+    ("{deadline:%Q}-{title}", "{deadline:%Q}-lab1"),
+    ("{title", "{title"),
+  ];
+  for &(template, rendered) in &cases { assert_eq!(render(template, var), rendered, "{}", template); }
+}
+
+#[test]
+fn semester_names() {
+  use crate::locale::Locale;
+  let (zh, en) = (Locale::Zh.names(), Locale::En.names());
+  let cases = [
+    ("2020-2021-1", "2020-2021-秋", "2020-2021-fall"),
+    ("2020-2021-2", "2020-2021-春", "2020-2021-spring"),
+    ("2020-2021-3", "2020-2021-夏", "2020-2021-summer"),
+    // unknown kinds are kept as they are
+    ("2020-2021-4", "2020-2021-4", "2020-2021-4"),
+    ("2020-2021-0", "2020-2021-0", "2020-2021-0"),
+    ("2020-2021-x", "2020-2021-x", "2020-2021-x"),
+    ("", "", ""),
+  ];
+  for &(id, name_zh, name_en) in &cases {
+    assert_eq!(crate::semester_name(id, zh), name_zh);
+    assert_eq!(crate::semester_name(id, en), name_en);
+  }
+}
+
+#[test]
+fn long_names() {
+  let mut m: Vec<(String, u64)> = Vec::new();
+  // 300 bytes, which are truncated to 255 on a character boundary
+  let long = "课".repeat(100);
+  crate::push_unique(&mut m, &long, 2);
+  crate::push_unique(&mut m, &long, 3);
+  crate::push_unique(&mut m, &"a".repeat(300), 4);
+  crate::push_unique(&mut m, "a/b", 5);
+  let names = m.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>();
+  assert_eq!(names, ["课".repeat(85), "课".repeat(83) + " (2)", "a".repeat(255), "a／b".to_owned()]);
+  assert!(names.iter().all(|x| x.len() <= 255));
+}
+
+#[test]
+fn api_content() {
+  let (mut learn, user) = login();
+  let uid = learn.uid;
+  let url = "https://learn.tsinghua.edu.cn/b/wlxt/kczy/zy/student/downloadFile/lab1";
+  fixture(&learn, user).data.lock().unwrap()["downloads"][url] = serde_json::json!([0xff, 0, 1]);
+  let (status, text) = api(&mut learn, uid, "GET", &format!("/{}/{}/作业/lab1/描述", STUDENT, OS), "");
+  assert_eq!(status, "200 OK");
+  assert_eq!((text["content"].as_str().unwrap().trim(), &text["encoding"]), ("实现 系统调用", &serde_json::json!("utf-8")));
+  // binary content is not valid UTF-8, so it is sent in base64 instead of being corrupted
+  let (_, binary) = api(&mut learn, uid, "GET", &format!("/{}/{}/作业/lab1/附件：lab1.pdf", STUDENT, OS), "");
+  assert_eq!(binary, serde_json::json!({ "content": "/wAB", "encoding": "base64" }));
+}
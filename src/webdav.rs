@@ -0,0 +1,121 @@
+use crate::{LearnFS, get_password, backend::Backend, http::{self, Request, Response}};
+use fuse::FileType::Directory;
+use libc::{c_int, ENOENT, ENOTDIR, EPERM};
+
+// a minimal WebDAV server for machines without FUSE, serving the tree of one student at `http://127.0.0.1:<port>/`
+// it is read-only, except that PUT to `<homework>/提交作业` submits the body as the content
+// PUT to any other name is forbidden, a client uploading a file expects it to be there afterwards, which is never true
+// other local users can connect to the port, so a random password generated on every start is required
+// WebDAV clients and browsers can't connect to a unix socket like `api`, so the uid of the peer can't be checked
+pub(crate) fn serve(mut learn: LearnFS, student: &str, port: u16) -> Result<(), String> {
+  let password = get_password(std::process::id(), learn.names.password_prompt).map_err(|e| e.to_string())?;
+  let uid = learn.uid;
+  let user = learn.login(student, &password, uid).map_err(|e| e.to_string())?;
+  let token = random_token().map_err(|e| e.to_string())?;
+  println!("WebDAV 服务地址：http://{}:{}@127.0.0.1:{}/", http::USER, token, port);
+  println!("用户名：{}，密码：{}", http::USER, token);
+  http::serve(port, &token, |req| respond(&mut learn, user, req)).map_err(|e| e.to_string())
+}
+
+// 128 random bits in hex
+fn random_token() -> std::io::Result<String> {
+  use std::io::Read;
+  let mut buf = [0u8; 16];
+  std::fs::File::open("/dev/urandom")?.read_exact(&mut buf)?;
+  Ok(buf.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+const ALLOW: &str = "OPTIONS, GET, HEAD, PUT, PROPFIND";
+
+fn error(e: c_int) -> Response {
+  let status = match e {
+    ENOENT => "404 Not Found",
+    ENOTDIR | EPERM | libc::EACCES => "403 Forbidden",
+    libc::EFBIG => "413 Payload Too Large",
+    libc::EAGAIN => "503 Service Unavailable",
+    libc::ETIMEDOUT => "504 Gateway Timeout",
+    _ => "502 Bad Gateway",
+  };
+  (status, Vec::new(), Vec::new())
+}
+
+pub(crate) fn respond<B: Backend>(learn: &mut LearnFS<B>, user: u64, req: &Request) -> Response {
+  let is_dir = |learn: &LearnFS<B>, ino| matches!(learn.attr(ino).kind, Directory);
+  match req.method.as_str() {
+    "OPTIONS" => ("200 OK", vec![("DAV", "1".to_owned()), ("Allow", ALLOW.to_owned())], Vec::new()),
+    "PROPFIND" => {
+      let ino = match learn.resolve(user, &req.path) { Ok(x) => x, Err(e) => return error(e) };
+      let mut entries = vec![(href(&req.path, is_dir(learn, ino)), ino)];
+      if is_dir(learn, ino) && req.depth.as_deref() != Some("0") {
+        let children = match learn.listing(ino) { Ok(x) => x, Err(e) => return error(e) };
+        for (name, x) in children { entries.push((href(&format!("{}/{}", req.path, name), is_dir(learn, x)), x)); }
+      }
+      let headers = vec![("Content-Type", "application/xml; charset=utf-8".to_owned())];
+      ("207 Multi-Status", headers, multistatus(learn, &entries).into_bytes())
+    }
+    "GET" | "HEAD" => {
+      let ino = match learn.resolve(user, &req.path) { Ok(x) => x, Err(e) => return error(e) };
+      if is_dir(learn, ino) {
+        // a listing for browsers, WebDAV clients use PROPFIND instead
+        let children = match learn.listing(ino) { Ok(x) => x, Err(e) => return error(e) };
+        let mut html = String::from("<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<ul>\n");
+        for (name, x) in children {
+          let href = href(&format!("{}/{}", req.path, name), is_dir(learn, x));
+          html += &format!("<li><a href=\"{}\">{}</a></li>\n", escape(&href), escape(&name));
+        }
+        html += "</ul>\n";
+        return ("200 OK", vec![("Content-Type", "text/html; charset=utf-8".to_owned())], html.into_bytes());
+      }
+      if let Err(e) = learn.load(ino) { return error(e); }
+      let data = learn.data(ino).map(<[u8]>::to_vec);
+      learn.unload(ino);
+      match data {
+        Some(data) => ("200 OK", vec![("Content-Type", "application/octet-stream".to_owned())], data),
+        None => error(EPERM),
+      }
+    }
+    "PUT" => {
+      let path = req.path.trim_end_matches('/');
+      let (parent, name) = match path.rfind('/') { Some(idx) => (&path[..idx], &path[idx + 1..]), None => ("", path) };
+      if name != learn.names.submit { return error(EPERM); }
+      let homework = match learn.resolve(user, parent) { Ok(x) => x, Err(e) => return error(e) };
+      let content = String::from_utf8_lossy(&req.body).into_owned();
+      match learn.submit(homework, content, None) { Ok(()) => ("204 No Content", Vec::new(), Vec::new()), Err(e) => error(e) }
+    }
+    _ => ("405 Method Not Allowed", vec![("Allow", ALLOW.to_owned())], Vec::new()),
+  }
+}
+
+fn multistatus<B: Backend>(learn: &LearnFS<B>, entries: &[(String, u64)]) -> String {
+  let mut x = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n");
+  for (href, ino) in entries {
+    let attr = learn.attr(*ino);
+    x += &format!("<D:response><D:href>{}</D:href><D:propstat><D:prop>", escape(href));
+    // all times are `UNIX_EPOCH`, the same as `fuse_fs`
+    x += "<D:getlastmodified>Thu, 01 Jan 1970 00:00:00 GMT</D:getlastmodified>";
+    if let Directory = attr.kind {
+      x += "<D:resourcetype><D:collection/></D:resourcetype>";
+    } else {
+      x += &format!("<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>", attr.size);
+    }
+    x += "</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n";
+  }
+  x + "</D:multistatus>\n"
+}
+
+// the percent-encoded href of `path`, directories end with `/`
+fn href(path: &str, dir: bool) -> String {
+  let mut ret = String::new();
+  for seg in path.split('/').filter(|x| !x.is_empty()) {
+    ret.push('/');
+    for &b in seg.as_bytes() {
+      if b.is_ascii_alphanumeric() || b"-._~".contains(&b) { ret.push(b as char); } else { ret += &format!("%{:02X}", b); }
+    }
+  }
+  if dir || ret.is_empty() { ret.push('/'); }
+  ret
+}
+
+fn escape(s: &str) -> String {
+  s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}